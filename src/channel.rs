@@ -0,0 +1,314 @@
+use std::f64::consts::PI;
+
+//SNR is measured in the 3 kHz bandwidth of an SSB receiver, as on HF
+const NOISE_BANDWIDTH_HZ: f64 = 3000.0;
+//fading taps are generated at this rate and interpolated, far above any HF Doppler spread
+const TAP_RATE_HZ: f64 = 200.0;
+const HILBERT_TAPS: usize = 255;
+
+
+//two path Watterson model, both paths with equal average power and a Gaussian Doppler spectrum
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fading {
+    //differential delay between the paths
+    pub delay_ms: f64,
+    //two sigma width of the Doppler spectrum
+    pub spread_hz: f64,
+}
+
+impl Fading {
+    //ITU-R F.1487 mid latitude conditions
+    pub fn preset(name: &str) -> Option<Fading> {
+        let (delay_ms, spread_hz) = match name.to_lowercase().as_str() {
+            "good" | "quiet" => (0.5, 0.1),
+            "moderate" => (1.0, 0.5),
+            "poor" | "disturbed" => (2.0, 1.0),
+            "flutter" => (0.5, 10.0),
+            _ => return None,
+        };
+        Some(Fading {delay_ms, spread_hz})
+    }
+}
+
+pub struct ChannelOptions {
+    //None leaves the signal noiseless
+    pub snr_db: Option<f64>,
+    pub offset_hz: f64,
+    pub fading: Option<Fading>,
+    //receiver sample clock error, positive records more samples per second
+    pub skew_ppm: f64,
+    pub seed: u64,
+}
+
+//xorshift64* with Box-Muller, keeps runs reproducible from the seed
+struct Gaussian {
+    state: u64,
+    spare: Option<f64>,
+}
+
+impl Gaussian {
+    fn new(seed: u64) -> Self {
+        Self {
+            state: seed.max(1),
+            spare: None,
+        }
+    }
+    fn uniform(&mut self) -> f64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let bits = self.state.wrapping_mul(0x2545F4914F6CDD1D) >> 11;
+        (bits as f64 + 0.5) / (1u64 << 53) as f64
+    }
+    fn next(&mut self) -> f64 {
+        if let Some(spare) = self.spare.take() {
+            return spare;
+        }
+        let r = (-2.0 * self.uniform().ln()).sqrt();
+        let theta = 2.0 * PI * self.uniform();
+        self.spare = Some(r * theta.sin());
+        r * theta.cos()
+    }
+}
+
+//analytic signal via a windowed FIR Hilbert transformer with centred taps, so neither part is delayed
+fn analytic(samples: &[f32]) -> Vec<(f64, f64)> {
+    //only odd offsets have a non zero tap
+    let half = (HILBERT_TAPS / 2) as isize;
+    let taps: Vec<(isize, f64)> = (-half..=half)
+        .filter(|n| n % 2 != 0)
+        .map(|n| {
+            let window = 0.54 + 0.46 * (PI * n as f64 / half as f64).cos();
+            (n, 2.0 / (PI * n as f64) * window)
+        })
+        .collect();
+
+    let at = |i: isize| -> f64 {
+        if i < 0 || i >= samples.len() as isize {0.0} else {samples[i as usize] as f64}
+    };
+    (0..samples.len() as isize)
+        .map(|i| {
+            let imag: f64 = taps.iter().map(|(n, t)| t * at(i - n)).sum();
+            (at(i), imag)
+        })
+        .collect()
+}
+
+//complex Gaussian tap gains with unit average power, one per output sample
+fn fading_taps(rng: &mut Gaussian, spread_hz: f64, len: usize, sample_rate: u32) -> Vec<(f64, f64)> {
+    //Gaussian kernel whose power spectrum has sigma spread_hz / 2
+    let sigma_f = (spread_hz / 2.0).max(1e-3);
+    let sigma_t = 1.0 / (2.0 * 2f64.sqrt() * PI * sigma_f) * TAP_RATE_HZ;
+    let half = (sigma_t * 4.0).ceil() as usize;
+    let kernel: Vec<f64> = (0..=2 * half)
+        .map(|k| (-((k as f64 - half as f64).powi(2)) / (2.0 * sigma_t * sigma_t)).exp())
+        .collect();
+    let norm = kernel.iter().map(|k| k * k).sum::<f64>().sqrt();
+
+    let steps = (len as f64 / sample_rate as f64 * TAP_RATE_HZ).ceil() as usize + 2;
+    let noise: Vec<(f64, f64)> = (0..steps + 2 * half)
+        .map(|_| (rng.next() / 2f64.sqrt(), rng.next() / 2f64.sqrt()))
+        .collect();
+    let gains: Vec<(f64, f64)> = (0..steps)
+        .map(|i| {
+            kernel.iter().zip(&noise[i..]).fold((0.0, 0.0), |acc, (k, n)| (acc.0 + k * n.0 / norm, acc.1 + k * n.1 / norm))
+        })
+        .collect();
+
+    (0..len)
+        .map(|n| {
+            let pos = n as f64 / sample_rate as f64 * TAP_RATE_HZ;
+            let i = pos as usize;
+            let frac = pos - i as f64;
+            let (a, b) = (gains[i], gains[i + 1]);
+            (a.0 + (b.0 - a.0) * frac, a.1 + (b.1 - a.1) * frac)
+        })
+        .collect()
+}
+
+//applies fading, tuning offset, noise and clock skew in the order the signal meets them
+pub fn simulate(samples: &[f32], sample_rate: u32, options: &ChannelOptions) -> Vec<f32> {
+    let mut rng = Gaussian::new(options.seed);
+    let signal = analytic(samples);
+
+    //sum of the faded paths, the second one delayed
+    let received: Vec<(f64, f64)> = match options.fading {
+        Some(fading) => {
+            let delay = (fading.delay_ms * sample_rate as f64 / 1000.0).round() as usize;
+            let paths = [
+                (0, fading_taps(&mut rng, fading.spread_hz, signal.len(), sample_rate)),
+                (delay, fading_taps(&mut rng, fading.spread_hz, signal.len(), sample_rate)),
+            ];
+            (0..signal.len())
+                .map(|n| {
+                    paths.iter().fold((0.0, 0.0), |acc, (delay, gains)| {
+                        let Some(s) = n.checked_sub(*delay).map(|i| signal[i]) else {
+                            return acc;
+                        };
+                        let g = gains[n];
+                        //both paths at half power keep the average level
+                        let (re, im) = (s.0 * g.0 - s.1 * g.1, s.0 * g.1 + s.1 * g.0);
+                        (acc.0 + re / 2f64.sqrt(), acc.1 + im / 2f64.sqrt())
+                    })
+                })
+                .collect()
+        }
+        None => signal,
+    };
+
+    //mixing the analytic signal moves the whole spectrum like a mistuned SSB receiver
+    let step = 2.0 * PI * options.offset_hz / sample_rate as f64;
+    let mut output: Vec<f64> = received
+        .iter()
+        .enumerate()
+        .map(|(n, s)| {
+            let phase = step * n as f64;
+            s.0 * phase.cos() - s.1 * phase.sin()
+        })
+        .collect();
+
+    if let Some(snr_db) = options.snr_db {
+        //average power while there is signal from the envelope, leading silence would understate it
+        let active: Vec<f64> = received
+            .iter()
+            .map(|s| (s.0 * s.0 + s.1 * s.1) / 2.0)
+            .filter(|&p| p > 1e-8)
+            .collect();
+        let power = active.iter().sum::<f64>() / active.len().max(1) as f64;
+        let noise_power = power / 10f64.powf(snr_db / 10.0) * (sample_rate as f64 / 2.0) / NOISE_BANDWIDTH_HZ;
+        let sigma = noise_power.sqrt();
+        for s in output.iter_mut() {
+            *s += rng.next() * sigma;
+        }
+    }
+
+    let ratio = 1.0 / (1.0 + options.skew_ppm / 1e6);
+    if ratio != 1.0 && output.len() > 1 {
+        let len = ((output.len() - 1) as f64 / ratio) as usize;
+        output = (0..len)
+            .map(|n| {
+                let pos = n as f64 * ratio;
+                let i = pos as usize;
+                let frac = pos - i as f64;
+                output[i] + (output[i + 1] - output[i]) * frac
+            })
+            .collect();
+    }
+
+    //scaled down only if noise or fading peaks would clip
+    let peak = output.iter().fold(0.0f64, |m, s| m.max(s.abs()));
+    let scale = if peak > 1.0 {1.0 / peak} else {1.0};
+    output.iter().map(|s| (s * scale) as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    fn tone(hz: f64, seconds: f64) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE as f64) as usize)
+            .map(|n| (0.25 * (2.0 * PI * hz * n as f64 / SAMPLE_RATE as f64).sin()) as f32)
+            .collect()
+    }
+
+    fn clean() -> ChannelOptions {
+        ChannelOptions {
+            snr_db: None,
+            offset_hz: 0.0,
+            fading: None,
+            skew_ppm: 0.0,
+            seed: 1,
+        }
+    }
+
+    //from the rising zero crossings between the first and last, interpolated between samples
+    fn frequency(samples: &[f32]) -> f64 {
+        let crossings: Vec<f64> = samples
+            .windows(2)
+            .enumerate()
+            .filter(|(_, w)| w[0] < 0.0 && w[1] >= 0.0)
+            .map(|(n, w)| n as f64 + (-w[0] / (w[1] - w[0])) as f64)
+            .collect();
+        let span = crossings[crossings.len() - 1] - crossings[0];
+        (crossings.len() - 1) as f64 / span * SAMPLE_RATE as f64
+    }
+
+    fn power(samples: &[f32]) -> f64 {
+        samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64
+    }
+
+    #[test]
+    fn noise_matches_snr_in_3khz() {
+        let signal = tone(1900.0, 10.0);
+        let options = ChannelOptions {
+            snr_db: Some(10.0),
+            ..clean()
+        };
+        let output = simulate(&signal, SAMPLE_RATE, &options);
+        let noise: Vec<f32> = output.iter().zip(&signal).map(|(o, s)| o - s).collect();
+        //white noise, only the part inside the 3 kHz bandwidth counts
+        let in_band = power(&noise) * NOISE_BANDWIDTH_HZ / (SAMPLE_RATE as f64 / 2.0);
+        let snr = 10.0 * (power(&signal) / in_band).log10();
+        assert!((snr - 10.0).abs() < 0.2, "measured {:.2} dB", snr);
+    }
+
+    #[test]
+    fn offset_moves_the_tone() {
+        let signal = tone(1500.0, 2.0);
+        for offset_hz in [-120.0, 75.0] {
+            let options = ChannelOptions {
+                offset_hz,
+                ..clean()
+            };
+            let output = simulate(&signal, SAMPLE_RATE, &options);
+            //the Hilbert filter needs a few taps to settle at either end
+            let measured = frequency(&output[1000..output.len() - 1000]);
+            assert!((measured - 1500.0 - offset_hz).abs() < 0.1, "{} Hz offset measured {:.2} Hz", offset_hz, measured);
+        }
+    }
+
+    #[test]
+    fn skew_stretches_time() {
+        let signal = tone(1000.0, 10.0);
+        let options = ChannelOptions {
+            skew_ppm: 1000.0,
+            ..clean()
+        };
+        let output = simulate(&signal, SAMPLE_RATE, &options);
+        assert_eq!(output.len(), ((signal.len() - 1) as f64 * 1.001) as usize);
+        //a fast receiver clock records the tone as lower
+        let measured = frequency(&output);
+        assert!((measured - 1000.0 / 1.001).abs() < 0.05, "measured {:.3} Hz", measured);
+    }
+
+    #[test]
+    fn fading_keeps_average_power_and_varies() {
+        let signal = tone(1900.0, 30.0);
+        let options = ChannelOptions {
+            fading: Fading::preset("poor"),
+            seed: 7,
+            ..clean()
+        };
+        let output = simulate(&signal, SAMPLE_RATE, &options);
+        let ratio = power(&output) / power(&signal);
+        assert!((0.4..2.5).contains(&ratio), "power ratio {:.2}", ratio);
+        //a 1 Hz Doppler spread fades deeply within a second or so
+        let windows: Vec<f64> = output.chunks(SAMPLE_RATE as usize / 10).map(power).collect();
+        let (low, high) = windows.iter().fold((f64::MAX, 0.0f64), |(l, h), &p| (l.min(p), h.max(p)));
+        assert!(high > 10.0 * low, "power between {:.4} and {:.4}", low, high);
+    }
+
+    #[test]
+    fn same_seed_same_channel() {
+        let signal = tone(1900.0, 1.0);
+        let options = ChannelOptions {
+            snr_db: Some(0.0),
+            fading: Fading::preset("moderate"),
+            seed: 42,
+            ..clean()
+        };
+        assert_eq!(simulate(&signal, SAMPLE_RATE, &options), simulate(&signal, SAMPLE_RATE, &options));
+    }
+}
@@ -0,0 +1,1214 @@
+use std::f64::consts::PI;
+
+use crate::{Error, SSTVMode};
+use crate::layout::{AVT_HEADER_BIT_MS, Channel, Layout, layout};
+
+//centre of the demodulator, middle of the 1100-2300 Hz SSTV band
+const CENTER_HZ: f64 = 1900.0;
+const CUTOFF_HZ: f64 = 1200.0;
+
+const VIS_LEADER_MS: f64 = 300.0;
+const VIS_BREAK_MS: f64 = 10.0;
+const VIS_BIT_MS: f64 = 30.0;
+//audio a header needs after the break before it is read, the second leader and the longest header
+const VIS_SEARCH_MS: f64 = VIS_LEADER_MS + 20.0 * VIS_BIT_MS;
+
+const AVT_HEADER_HZ: f64 = 1700.0;
+
+
+#[derive(Debug)]
+pub enum VisError {
+    NotFound,
+    Parity(u16),
+    UnknownCode(u16),
+    //neither line syncs nor a picture start were found
+    NoPicture,
+}
+impl std::fmt::Display for VisError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            VisError::NotFound => write!(f, "No VIS header found"),
+            VisError::Parity(code) => write!(f, "VIS parity error (read code {:#06x})", code),
+            VisError::UnknownCode(code) => write!(f, "Unknown VIS code {:#06x}", code),
+            VisError::NoPicture => write!(f, "No picture found"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct DecodeOptions {
+    //None reads the mode from the VIS header
+    pub mode: Option<SSTVMode>,
+    //fit the line syncs and resample away sample clock error
+    pub slant_correction: bool,
+    //place lines by the nominal line period from the end of the VIS header, ignoring line syncs
+    //without a header the first line is found from the syncs that survive
+    pub free_running: bool,
+    //adjust the free-running line period by cross-correlating adjacent lines
+    pub correlate: bool,
+}
+
+pub struct Decoded {
+    pub mode: SSTVMode,
+    pub image: image::RgbImage,
+    pub clock_error_ppm: Option<f64>,
+}
+
+
+pub fn read_wav(path: &str) -> Result<(Vec<f32>, u32), Error> {
+    let mut reader = hound::WavReader::open(path).map_err(Error::InputAudio)?;
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .collect::<Result<_, _>>()
+            .map_err(Error::InputAudio)?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()
+                .map_err(Error::InputAudio)?
+        }
+    };
+
+    //only the first channel is decoded
+    let samples = interleaved.iter().step_by(channels).copied().collect();
+    Ok((samples, spec.sample_rate))
+}
+
+//second order low pass section, RBJ cookbook coefficients
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+impl Biquad {
+    fn low_pass(sample_rate: f64, cutoff_hz: f64, q: f64) -> Self {
+        let w0 = 2.0 * PI * cutoff_hz / sample_rate;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos_w0 = w0.cos();
+        let a0 = 1.0 + alpha;
+        Self {
+            b0: (1.0 - cos_w0) / 2.0 / a0,
+            b1: (1.0 - cos_w0) / a0,
+            b2: (1.0 - cos_w0) / 2.0 / a0,
+            a1: -2.0 * cos_w0 / a0,
+            a2: (1.0 - alpha) / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+//4th order butterworth built from two biquads
+struct LowPass {
+    stages: [Biquad; 2],
+}
+impl LowPass {
+    fn new(sample_rate: f64, cutoff_hz: f64) -> Self {
+        Self {
+            stages: [
+                Biquad::low_pass(sample_rate, cutoff_hz, 0.5412),
+                Biquad::low_pass(sample_rate, cutoff_hz, 1.3066),
+            ],
+        }
+    }
+    fn process(&mut self, x: f64) -> f64 {
+        let x = self.stages[0].process(x);
+        self.stages[1].process(x)
+    }
+}
+
+//instantaneous frequency in Hz of every sample
+pub fn demodulate(samples: &[f32], sample_rate: u32) -> Vec<f32> {
+    let mut freq = Vec::with_capacity(samples.len());
+    Demodulator::new(sample_rate).process(samples, &mut freq);
+    freq
+}
+
+//keeps the filters and the local oscillator between blocks, so audio can be demodulated as it arrives
+struct Demodulator {
+    sample_rate: f64,
+    lp_i: LowPass,
+    lp_q: LowPass,
+    //local oscillator as a rotating phasor, renormalised now and then
+    step: (f64, f64),
+    lo: (f64, f64),
+    prev: (f64, f64),
+    n: usize,
+}
+impl Demodulator {
+    fn new(sample_rate: u32) -> Self {
+        let sr = sample_rate as f64;
+        let cutoff_hz = CUTOFF_HZ.min(sr * 0.45);
+        Self {
+            sample_rate: sr,
+            lp_i: LowPass::new(sr, cutoff_hz),
+            lp_q: LowPass::new(sr, cutoff_hz),
+            step: (-2.0 * PI * CENTER_HZ / sr).sin_cos(),
+            lo: (0.0, 1.0),
+            prev: (0.0, 0.0),
+            n: 0,
+        }
+    }
+    fn process(&mut self, samples: &[f32], freq: &mut Vec<f32>) {
+        let (step, sr) = (self.step, self.sample_rate);
+        for &x in samples {
+            let x = x as f64;
+            let i = self.lp_i.process(x * self.lo.1);
+            let q = self.lp_q.process(x * self.lo.0);
+
+            //phase difference to the previous sample
+            let re = i * self.prev.0 + q * self.prev.1;
+            let im = q * self.prev.0 - i * self.prev.1;
+            let dphi = im.atan2(re);
+            freq.push((CENTER_HZ + dphi * sr / (2.0 * PI)) as f32);
+            self.prev = (i, q);
+
+            let lo = self.lo;
+            self.lo = (lo.0 * step.1 + lo.1 * step.0, lo.1 * step.1 - lo.0 * step.0);
+            if self.n.is_multiple_of(1024) {
+                let norm = (self.lo.0 * self.lo.0 + self.lo.1 * self.lo.1).sqrt();
+                self.lo = (self.lo.0 / norm, self.lo.1 / norm);
+            }
+            self.n += 1;
+        }
+    }
+}
+
+//running sums of the frequency track so any window can be averaged in O(1)
+struct Track {
+    prefix: Vec<f64>,
+}
+impl Track {
+    fn new(freq: &[f32]) -> Self {
+        let mut track = Self {prefix: Vec::with_capacity(freq.len() + 1)};
+        track.prefix.push(0.0);
+        track.extend(freq);
+        track
+    }
+    fn extend(&mut self, freq: &[f32]) {
+        let mut sum = self.prefix[self.prefix.len() - 1];
+        for &f in freq {
+            sum += f as f64;
+            self.prefix.push(sum);
+        }
+    }
+    //forgets the first n samples, only differences of the sums are ever used
+    fn drain(&mut self, n: usize) {
+        self.prefix.drain(..n);
+    }
+    fn len(&self) -> usize {
+        self.prefix.len() - 1
+    }
+    //mean frequency between two fractional sample positions
+    fn mean(&self, from: f64, to: f64) -> Option<f64> {
+        let lo = from.round();
+        let hi = to.round().max(lo + 1.0);
+        if lo < 0.0 || hi > self.len() as f64 {
+            return None;
+        }
+        let (lo, hi) = (lo as usize, hi as usize);
+        Some((self.prefix[hi] - self.prefix[lo]) / (hi - lo) as f64)
+    }
+}
+
+//runs of sync frequency at least min_len samples long, as (start, end) sample indices
+fn sync_runs(freq: &[f32], mode: &SSTVMode, min_len: usize) -> Vec<(usize, usize)> {
+    let threshold = (mode.sync_hz() + mode.frequency_range().0) / 2.0;
+    let mut runs = Vec::new();
+    let mut start = None;
+    for (n, &f) in freq.iter().enumerate() {
+        match (f < threshold, start) {
+            (true, None) => start = Some(n),
+            (false, Some(s)) => {
+                if n - s >= min_len {
+                    runs.push((s, n));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start && freq.len() - s >= min_len {
+        runs.push((s, freq.len()));
+    }
+    runs
+}
+
+//sync end closest to target within tolerance
+fn nearest(ends: &[f64], target: f64, tolerance: f64) -> Option<f64> {
+    let i = ends.partition_point(|&e| e < target - tolerance);
+    ends[i..]
+        .iter()
+        .take_while(|&&e| e <= target + tolerance)
+        .min_by(|a, b| (*a - target).abs().total_cmp(&(*b - target).abs()))
+        .copied()
+}
+
+struct Lines {
+    starts: Vec<f64>,
+    //line number and trailing edge of every sync that was actually found
+    syncs: Vec<(usize, f64)>,
+}
+
+//start sample of every line, locked onto the trailing edge of each line sync
+fn track_lines(freq: &[f32], sample_rate: u32, mode: &SSTVMode, layout: &Layout, lines: usize, from: f64) -> Option<Lines> {
+    let ms = sample_rate as f64 / 1000.0;
+    let (sync_offset_ms, sync_ms) = layout.sync?;
+    let period = layout.period_ms * ms;
+    let tolerance = (sync_ms * 0.5).max(1.0) * ms;
+
+    //a sync merged with the VIS stop bit is longer than nominal, but its trailing edge is still in place
+    let ends: Vec<f64> = sync_runs(freq, mode, (sync_ms * 0.5 * ms) as usize)
+        .iter()
+        .map(|r| r.1 as f64)
+        .filter(|&e| e > from)
+        .collect();
+
+    //first sync followed by two more at the line period
+    let mut first = *ends.iter().find(|&&e| {
+        nearest(&ends, e + period, tolerance).is_some() && nearest(&ends, e + 2.0 * period, tolerance).is_some()
+    })?;
+    while let Some(e) = nearest(&ends, first - period, tolerance).filter(|&e| e > from) {
+        first = e;
+    }
+
+    let mut starts = Vec::with_capacity(lines);
+    let mut syncs = Vec::with_capacity(lines);
+    let mut expected = first;
+    for line in 0..lines {
+        let found = nearest(&ends, expected, tolerance);
+        if let Some(end) = found {
+            syncs.push((line, end));
+        }
+        let end = found.unwrap_or(expected);
+        starts.push(end - (sync_offset_ms + sync_ms) * ms);
+        expected = end + period;
+    }
+    Some(Lines {starts, syncs})
+}
+
+//least squares slope of sync position over line number, refitted once without outliers
+fn fit_line_period(syncs: &[(usize, f64)], tolerance: f64) -> Option<f64> {
+    let fit = |points: &[(usize, f64)]| -> Option<(f64, f64)> {
+        if points.len() < 10 {
+            return None;
+        }
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|p| p.0 as f64).sum::<f64>() / n;
+        let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+        let sxy: f64 = points.iter().map(|p| (p.0 as f64 - mean_x) * (p.1 - mean_y)).sum();
+        let sxx: f64 = points.iter().map(|p| (p.0 as f64 - mean_x).powi(2)).sum();
+        let slope = sxy / sxx;
+        Some((mean_y - slope * mean_x, slope))
+    };
+    let (offset, slope) = fit(syncs)?;
+    let inliers: Vec<(usize, f64)> = syncs
+        .iter()
+        .copied()
+        .filter(|p| (p.1 - (offset + slope * p.0 as f64)).abs() < tolerance)
+        .collect();
+    fit(&inliers).map(|(_, slope)| slope)
+}
+
+//line period that best lines up each line with the lines before it, first against the line just before
+//and then against ones further back to average out the drift of the picture content itself
+fn correlate_period(freq: &[f32], from: f64, period: f64, lines: usize) -> Option<f64> {
+    //enough lag for about 2000 ppm of clock error
+    let max_lag = (period * 0.002).ceil() as isize + 1;
+    let len = period as usize;
+    let line_at = |start: f64| -> Option<Vec<f64>> {
+        let start = start.round() as usize;
+        let samples = freq.get(start..start + len)?;
+        let mean = samples.iter().map(|&f| f as f64).sum::<f64>() / len as f64;
+        Some(samples.iter().map(|&f| f as f64 - mean).collect())
+    };
+
+    //median lag between lines stride apart when placed every line_period
+    let median_lag = |line_period: f64, stride: usize| -> Option<f64> {
+        let mut lags = Vec::with_capacity(lines);
+        for line in stride..lines {
+            let (Some(prev), Some(cur)) = (line_at(from + (line - stride) as f64 * line_period), line_at(from + line as f64 * line_period)) else {
+                break;
+            };
+            let corr = |lag: isize| -> f64 {
+                (max_lag..len as isize - max_lag)
+                    .map(|n| prev[n as usize] * cur[(n + lag) as usize])
+                    .sum()
+            };
+            let scores: Vec<f64> = (-max_lag..=max_lag).map(corr).collect();
+            let best = (0..scores.len()).max_by(|&a, &b| scores[a].total_cmp(&scores[b]))?;
+            //parabolic interpolation around the peak for a sub-sample lag
+            let mut lag = best as f64 - max_lag as f64;
+            if best > 0 && best < scores.len() - 1 {
+                let (a, b, c) = (scores[best - 1], scores[best], scores[best + 1]);
+                let denom = a - 2.0 * b + c;
+                if denom != 0.0 {
+                    lag += 0.5 * (a - c) / denom;
+                }
+            }
+            lags.push(lag);
+        }
+        if lags.len() < 10 {
+            return None;
+        }
+        lags.sort_by(f64::total_cmp);
+        Some(lags[lags.len() / 2])
+    };
+
+    let mut line_period = period + median_lag(period, 1)?;
+    for stride in [4, 16, 64] {
+        match median_lag(line_period, stride) {
+            Some(lag) => line_period += lag / stride as f64,
+            None => break,
+        }
+    }
+    Some(line_period)
+}
+
+//start of the first line when there is no VIS header to count from
+//the sync phase comes from folding the whole recording at the line period, so syncs lost to noise only thin it out,
+//then the picture goes where the most of its lines have a sync
+fn find_first_line(track: &Track, sample_rate: u32, mode: &SSTVMode, layout: &Layout, lines: usize) -> Option<f64> {
+    let ms = sample_rate as f64 / 1000.0;
+    let (sync_offset_ms, sync_ms) = layout.sync?;
+    let period = layout.period_ms * ms;
+    let (offset, len) = (sync_offset_ms * ms, sync_ms * ms);
+    let threshold = ((mode.sync_hz() + mode.frequency_range().0) / 2.0) as f64;
+    let sync_at = |start: f64| track.mean(start + offset, start + offset + len);
+    //lines whose sync fits in the recording from a given phase
+    let fitting = |phase: f64| ((track.len() as f64 - phase - offset - len) / period).floor() as i64 + 1;
+    let count = fitting(period);
+    if count <= 0 {
+        return None;
+    }
+
+    //anything above the threshold counts the same, a missing sync must not pull the phase
+    let phase = (0..period.ceil() as usize)
+        .map(|p| {
+            let score: f64 = (0..count)
+                .map(|k| sync_at(p as f64 + k as f64 * period).map_or(threshold, |f| f.min(threshold)))
+                .sum();
+            (p as f64, score)
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))?
+        .0;
+    let count = fitting(phase);
+
+    //lines with a sync so far, the picture may have begun before the recording did
+    let mut hits = vec![0usize; count as usize + 1];
+    for k in 0..count as usize {
+        let hit = sync_at(phase + k as f64 * period).is_some_and(|f| f < threshold);
+        hits[k + 1] = hits[k] + hit as usize;
+    }
+    let hits_before = |k: i64| hits[k.clamp(0, count) as usize];
+    //where lost syncs leave a tie, the picture is kept inside the recording
+    let (first, found, _) = (1 - lines as i64..count)
+        .map(|k| {
+            let end = k + lines as i64;
+            (k, hits_before(end) - hits_before(k), end.min(count) - k.max(0))
+        })
+        .fold((0, 0, 0), |best, cur| if (cur.1, cur.2) > (best.1, best.2) {cur} else {best});
+    (found > 0).then_some(phase + first as f64 * period)
+}
+
+//linear interpolation, ratio is input samples per output sample
+fn resample(samples: &[f32], ratio: f64) -> Vec<f32> {
+    let len = ((samples.len() - 1) as f64 / ratio) as usize;
+    (0..len)
+        .map(|n| {
+            let pos = n as f64 * ratio;
+            let i = pos as usize;
+            let frac = (pos - i as f64) as f32;
+            samples[i] + (samples[(i + 1).min(samples.len() - 1)] - samples[i]) * frac
+        })
+        .collect()
+}
+
+//AVT has no line sync, the image starts right after the counted down header
+fn find_avt_start(track: &Track, sample_rate: u32, from: f64) -> Option<f64> {
+    let bit = AVT_HEADER_BIT_MS * sample_rate as f64 / 1000.0;
+    let read_word = |t: f64| -> Option<u16> {
+        let mut word = 0u16;
+        for k in 0..16 {
+            let from = t + (k as f64 + 0.25) * bit;
+            let f = track.mean(from, from + bit * 0.5)?;
+            if f > AVT_HEADER_HZ {
+                word |= 1 << k;
+            }
+        }
+        Some(word)
+    };
+    let count_of = |word: u16| -> Option<u8> {
+        let (low, high) = ((word & 0xFF) as u8, (word >> 8) as u8);
+        if low ^ high == 0xFF && low < 32 {Some(low)} else {None}
+    };
+
+    let step = (bit / 4.0).max(1.0);
+    let mut t = from;
+    while t + 32.0 * bit < track.len() as f64 {
+        //neighbouring words have to continue the countdown, calibration tones can mimic a single word
+        let counts_down = |count: u8| -> bool {
+            (1..=2).all(|k: u8| {
+                let later = count < k || read_word(t + k as f64 * 16.0 * bit).and_then(count_of) == Some(count - k);
+                let earlier = count + k > 31 || read_word(t - k as f64 * 16.0 * bit).and_then(count_of) == Some(count + k);
+                later && earlier
+            })
+        };
+        if let Some(count) = read_word(t).and_then(count_of)
+            && counts_down(count)
+        {
+            //fine alignment: bit windows line up when they average furthest from the decision level
+            //the first word is skipped, the VIS stop bit before it would pull the windows early
+            let first = if count == 31 {16} else {-16 * (31 - count as i32).min(3)};
+            let last = 16 * (count as i32 + 1).min(4);
+            let score = |t: f64| -> f64 {
+                (first..last)
+                    .filter_map(|k| track.mean(t + k as f64 * bit, t + (k + 1) as f64 * bit))
+                    .map(|f| (f - AVT_HEADER_HZ).abs())
+                    .sum()
+            };
+            let mut best = t;
+            let mut best_score = f64::MIN;
+            //the coarse scan hits as soon as the bit centres fit, up to a quarter bit early
+            let mut fine = t - step;
+            while fine <= t + 2.0 * step {
+                let s = score(fine);
+                if s > best_score {
+                    best = fine;
+                    best_score = s;
+                }
+                fine += 1.0;
+            }
+            return Some(best + (count as f64 + 1.0) * 16.0 * bit);
+        }
+        t += step;
+    }
+    None
+}
+
+fn render(track: &Track, sample_rate: u32, mode: &SSTVMode, layout: &Layout, starts: &[f64]) -> image::RgbImage {
+    let mut picture = Picture::new(mode);
+    for (line, &start) in starts.iter().enumerate() {
+        picture.line(track, sample_rate, layout, line, start);
+    }
+    picture.image(layout)
+}
+
+//picture filled in one line at a time
+struct Picture {
+    width: usize,
+    height: usize,
+    black: f64,
+    white: f64,
+    //planes: red, green, blue, luma, R-Y, B-Y
+    planes: Vec<Vec<f32>>,
+    has_diff: [Vec<bool>; 2],
+}
+
+impl Picture {
+    fn new(mode: &SSTVMode) -> Self {
+        let (width, height) = mode.resolution();
+        let (width, height) = (width as usize, height as usize);
+        let (black, white) = mode.frequency_range();
+        Self {
+            width,
+            height,
+            black: black as f64,
+            white: white as f64,
+            planes: vec![vec![0.0f32; width * height]; 6],
+            has_diff: [vec![false; height], vec![false; height]],
+        }
+    }
+
+    fn line(&mut self, track: &Track, sample_rate: u32, layout: &Layout, line: usize, start: f64) {
+        let (width, height, black, white) = (self.width, self.height, self.black, self.white);
+        let ms = sample_rate as f64 / 1000.0;
+        let (planes, has_diff) = (&mut self.planes, &mut self.has_diff);
+        for scan in &layout.scans {
+            let pixel = scan.duration_ms / scan.samples as f64;
+            let rows: Vec<usize> = match scan.row {
+                Some(r) => vec![line * layout.rows + r],
+                None => (0..layout.rows).map(|r| line * layout.rows + r).collect(),
+            };
+            let channel = match scan.channel {
+                Channel::AltDiff => if (line * layout.rows).is_multiple_of(2) {Channel::RedDiff} else {Channel::BlueDiff},
+                c => c,
+            };
+            let plane = match channel {
+                Channel::Red => 0,
+                Channel::Green => 1,
+                Channel::Blue => 2,
+                Channel::Luma => 3,
+                Channel::RedDiff => 4,
+                _ => 5,
+            };
+            for x in 0..scan.samples {
+                let from = start + (scan.start_ms + x as f64 * pixel) * ms;
+                let Some(f) = track.mean(from, from + pixel * ms) else {
+                    continue;
+                };
+                let value = if plane >= 4 {
+                    (f - (black + white) / 2.0) / ((white - black) / 2.0)
+                }
+                else {
+                    ((f - black) / (white - black)).clamp(0.0, 1.0)
+                };
+                //subsampled scans cover several pixels each
+                let span = width / scan.samples;
+                for &row in rows.iter().filter(|&&r| r < height) {
+                    for px in x * span..(x + 1) * span {
+                        planes[plane][row * width + px] = value as f32;
+                    }
+                    if plane >= 4 {
+                        has_diff[plane - 4][row] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    //picture as received so far
+    fn image(&self, layout: &Layout) -> image::RgbImage {
+        let (width, height) = (self.width, self.height);
+        let mut planes = self.planes.clone();
+        //rows without their own chrominance borrow it from the nearest row that has it
+        for (d, has) in self.has_diff.iter().enumerate() {
+            for row in 0..height {
+                if has[row] {
+                    continue;
+                }
+                let source = (1..height)
+                    .flat_map(|k| [row.checked_sub(k), Some(row + k)])
+                    .flatten()
+                    .find(|&r| r < height && has[r]);
+                if let Some(source) = source {
+                    let (a, b) = (source * width, row * width);
+                    let copied = planes[4 + d][a..a + width].to_vec();
+                    planes[4 + d][b..b + width].copy_from_slice(&copied);
+                }
+            }
+        }
+
+        let channels: Vec<Channel> = layout.scans.iter().map(|s| s.channel).collect();
+        let rgb = channels.contains(&Channel::Red);
+        let chroma = channels.iter().any(|c| matches!(c, Channel::RedDiff | Channel::BlueDiff | Channel::AltDiff));
+
+        let to_u8 = |v: f32| (v * 255.0).round().clamp(0.0, 255.0) as u8;
+        image::RgbImage::from_fn(width as u32, height as u32, |x, y| {
+            let i = y as usize * width + x as usize;
+            if rgb {
+                image::Rgb([to_u8(planes[0][i]), to_u8(planes[1][i]), to_u8(planes[2][i])])
+            }
+            else if chroma {
+                let (r, g, b) = crate::ycrcb_to_rgb(planes[3][i], planes[4][i], planes[5][i]);
+                image::Rgb([r, g, b])
+            }
+            else {
+                let v = to_u8(planes[3][i]);
+                image::Rgb([v, v, v])
+            }
+        })
+    }
+}
+
+//mode and the sample where the stop bit ends
+type Vis = Result<(SSTVMode, f64), VisError>;
+
+//inverse of write_vis
+fn detect_vis(track: &Track, sample_rate: u32) -> Vis {
+    scan_vis(track, sample_rate, 0.0).map_or(Err(VisError::NotFound), |(_, header)| header)
+}
+
+//first VIS header from sample from on, with the position of the break before it so a rejected header can be skipped
+fn scan_vis(track: &Track, sample_rate: u32, from: f64) -> Option<(f64, Vis)> {
+    let ms = sample_rate as f64 / 1000.0;
+    let near = |f: f64, hz: f32, tolerance: f64| (f - hz as f64).abs() < tolerance;
+
+    for narrow in [false, true] {
+        let (leader_hz, bit_1_hz, bit_0_hz, bit_n_hz) = crate::vis_tones(narrow);
+        let bit_tolerance = ((bit_0_hz - bit_1_hz).abs() / 2.0) as f64;
+        let tone_tolerance = 2.0 * bit_tolerance;
+        let edge_hz = ((leader_hz + bit_n_hz) / 2.0) as f64;
+        //mean over the middle half of a bit
+        let bit_at = |start: f64, k: usize| track.mean(start + (k as f64 + 0.25) * VIS_BIT_MS * ms, start + (k as f64 + 0.75) * VIS_BIT_MS * ms);
+
+        let mut t = from.max(VIS_LEADER_MS * ms);
+        while t + VIS_SEARCH_MS * ms < track.len() as f64 {
+            //break between the two leaders
+            let is_break = track.mean(t, t + VIS_BREAK_MS * ms).is_some_and(|f| near(f, bit_n_hz, tone_tolerance))
+                && track.mean(t - 100.0 * ms, t).is_some_and(|f| near(f, leader_hz, tone_tolerance))
+                && track.mean(t + (VIS_BREAK_MS + 10.0) * ms, t + (VIS_BREAK_MS + 110.0) * ms).is_some_and(|f| near(f, leader_hz, tone_tolerance));
+            if !is_break {
+                t += ms;
+                continue;
+            }
+
+            //leading edge of the start bit, where the second leader drops away
+            let mut start = t + (VIS_BREAK_MS + VIS_LEADER_MS * 0.8) * ms;
+            let limit = t + (VIS_BREAK_MS + VIS_LEADER_MS * 1.2) * ms;
+            while start < limit && track.mean(start, start + ms).is_some_and(|f| f > edge_hz) {
+                start += 1.0;
+            }
+            //the window mean crosses over when the window is centred on the edge
+            start += 0.5 * ms;
+            if start >= limit || !bit_at(start, 0).is_some_and(|f| near(f, bit_n_hz, bit_tolerance)) {
+                t += VIS_BREAK_MS * ms;
+                continue;
+            }
+
+            let header = || -> Vis {
+                //data bits LSB first
+                let read_bits = |first: usize, count: usize| -> Result<u16, VisError> {
+                    let mut data = 0u16;
+                    for k in 0..count {
+                        let f = bit_at(start, first + k).ok_or(VisError::NotFound)?;
+                        if (f - bit_1_hz as f64).abs() < (f - bit_0_hz as f64).abs() {
+                            data |= 1 << k;
+                        }
+                    }
+                    Ok(data)
+                };
+
+                //7 bits and an even parity bit
+                let byte = read_bits(1, 8)?;
+                let mut code = byte & 0x7F;
+                let mut stop = 9;
+                if !byte.count_ones().is_multiple_of(2) {
+                    //MMSSTV's extended VIS: the 0x23 marker fails parity on purpose and the mode byte follows without one
+                    if byte != 0x23 {
+                        return Err(VisError::Parity(code));
+                    }
+                    code |= read_bits(9, 8)? << 8;
+                    stop = 17;
+                }
+
+                if !bit_at(start, stop).is_some_and(|f| near(f, bit_n_hz, bit_tolerance)) {
+                    return Err(VisError::NotFound);
+                }
+                let mode = SSTVMode::from_vis_code(code)
+                    .filter(|mode| mode.is_narrow() == narrow)
+                    .ok_or(VisError::UnknownCode(code))?;
+                Ok((mode, start + (stop + 1) as f64 * VIS_BIT_MS * ms))
+            };
+            return Some((t, header()));
+        }
+    }
+    None
+}
+
+pub fn decode(samples: &[f32], sample_rate: u32, options: &DecodeOptions) -> Result<Decoded, VisError> {
+    let ms = sample_rate as f64 / 1000.0;
+    let mut freq = demodulate(samples, sample_rate);
+    let mut track = Track::new(&freq);
+    //the VIS header still marks where the picture starts when the mode is given
+    let (mode, vis_end) = match (options.mode, detect_vis(&track, sample_rate)) {
+        (Some(mode), Ok((_, end))) => (mode, Some(end)),
+        (Some(mode), Err(_)) => (mode, None),
+        (None, vis) => vis.map(|(mode, end)| (mode, Some(end)))?,
+    };
+    let from = vis_end.unwrap_or(0.0);
+    let layout = layout(&mode);
+    let lines = mode.resolution().1 as usize / layout.rows;
+    let period = layout.period_ms * ms;
+
+    let mut clock_error_ppm = None;
+    let starts = match layout.sync {
+        Some(_) if options.free_running => {
+            let lead = layout.lead_ms * ms;
+            //without a header the surviving line syncs have to place the picture
+            let first_line = |track: &Track| match vis_end {
+                Some(_) => detect_vis(track, sample_rate).ok().map(|(_, end)| end + lead),
+                None => find_first_line(track, sample_rate, &mode, &layout, lines),
+            };
+            let mut start = match vis_end {
+                Some(end) => end + lead,
+                None => find_first_line(&track, sample_rate, &mode, &layout, lines).ok_or(VisError::NoPicture)?,
+            };
+            let line_period = options
+                .correlate
+                .then(|| correlate_period(&freq, start, period, lines))
+                .flatten();
+            //resample as for slant correction, the picture start is found again to keep it in step
+            if let Some(line_period) = line_period {
+                let ratio = line_period / period;
+                clock_error_ppm = Some((ratio - 1.0) * 1e6);
+                freq = demodulate(&resample(samples, ratio), sample_rate);
+                track = Track::new(&freq);
+                start = first_line(&track).unwrap_or(start / ratio);
+            }
+            Some((0..lines).map(|i| start + i as f64 * period).collect())
+        }
+        Some((_, sync_ms)) => {
+            let mut found = track_lines(&freq, sample_rate, &mode, &layout, lines, from);
+            let slope = found
+                .as_ref()
+                .filter(|_| options.slant_correction)
+                .and_then(|found| fit_line_period(&found.syncs, sync_ms * 0.5 * ms));
+            //a slower or faster sample clock stretches every line by the same ratio
+            if let Some(slope) = slope {
+                let ratio = slope / period;
+                clock_error_ppm = Some((ratio - 1.0) * 1e6);
+                freq = demodulate(&resample(samples, ratio), sample_rate);
+                track = Track::new(&freq);
+                found = track_lines(&freq, sample_rate, &mode, &layout, lines, from / ratio);
+            }
+            found.map(|found| found.starts)
+        }
+        None => find_avt_start(&track, sample_rate, from)
+            .map(|start| (0..lines).map(|i| start + i as f64 * period).collect()),
+    };
+    let starts = starts.ok_or(VisError::NoPicture)?;
+    Ok(Decoded {
+        mode,
+        image: render(&track, sample_rate, &mode, &layout, &starts),
+        clock_error_ppm,
+    })
+}
+
+pub enum StreamEvent {
+    //VIS header received
+    Started(SSTVMode),
+    //picture so far, with the number of image rows received
+    Partial(Decoded, u32),
+    Finished(Decoded),
+}
+
+//decodes pictures as audio arrives, samples are pushed in blocks of any size
+//only new audio is demodulated and each line is placed and drawn once, the finished picture is decoded once more in full
+pub struct StreamDecoder {
+    sample_rate: u32,
+    options: DecodeOptions,
+    //rows between partial pictures
+    update_rows: u32,
+    demodulator: Demodulator,
+    //audio and its frequency track since the first sample still needed
+    samples: Vec<f32>,
+    freq: Vec<f32>,
+    track: Track,
+    //where the search for the next VIS header goes on, rejected headers lie before it
+    searched: f64,
+    receiving: Option<Receiving>,
+}
+
+//picture locked onto by its VIS header
+struct Receiving {
+    mode: SSTVMode,
+    layout: Layout,
+    lines: usize,
+    //end of the VIS header
+    from: f64,
+    //start of every line placed so far, the next one is expected a line period after the last
+    starts: Vec<f64>,
+    picture: Picture,
+    //rows reported so far
+    reported: u32,
+}
+
+impl StreamDecoder {
+    pub fn new(sample_rate: u32, options: DecodeOptions, update_rows: u32) -> Self {
+        Self {
+            sample_rate,
+            options,
+            update_rows: update_rows.max(1),
+            demodulator: Demodulator::new(sample_rate),
+            samples: Vec::new(),
+            freq: Vec::new(),
+            track: Track::new(&[]),
+            searched: 0.0,
+            receiving: None,
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) -> Vec<StreamEvent> {
+        let ms = self.sample_rate as f64 / 1000.0;
+        self.samples.extend_from_slice(samples);
+        let old = self.freq.len();
+        self.demodulator.process(samples, &mut self.freq);
+        self.track.extend(&self.freq[old..]);
+
+        let mut events = Vec::new();
+        loop {
+            let Some(receiving) = &mut self.receiving else {
+                match scan_vis(&self.track, self.sample_rate, self.searched) {
+                    Some((at, Ok((mode, end)))) => {
+                        //the header stays in the buffer so decode() finds the picture start again
+                        let keep = (at - VIS_LEADER_MS * ms).max(0.0) as usize;
+                        self.drain(keep);
+                        let mode = self.options.mode.unwrap_or(mode);
+                        let layout = layout(&mode);
+                        self.receiving = Some(Receiving {
+                            mode,
+                            lines: mode.resolution().1 as usize / layout.rows,
+                            layout,
+                            from: end - keep as f64,
+                            starts: Vec::new(),
+                            picture: Picture::new(&mode),
+                            reported: 0,
+                        });
+                        events.push(StreamEvent::Started(mode));
+                        continue;
+                    }
+                    //a broken header is skipped, not found again on the next block
+                    Some((at, Err(_))) => {
+                        self.searched = at + VIS_BREAK_MS * ms;
+                        continue;
+                    }
+                    None => {
+                        //everything before the leader of a header that could still start is dropped
+                        self.searched = self.searched.max(self.track.len() as f64 - VIS_SEARCH_MS * ms);
+                        self.drain((self.searched - VIS_LEADER_MS * ms).max(0.0) as usize);
+                        break;
+                    }
+                }
+            };
+
+            let placed = receiving.place_lines(&self.freq, &self.track, self.sample_rate, self.options.free_running);
+            let rows = (receiving.starts.len() * receiving.layout.rows) as u32;
+            if receiving.starts.len() == receiving.lines {
+                let end = *receiving.starts.last().unwrap() + receiving.layout.period_ms * ms;
+                if let Some(decoded) = self.finish() {
+                    events.push(StreamEvent::Finished(decoded));
+                }
+                self.drain(end as usize);
+                continue;
+            }
+            if placed && rows >= receiving.reported + self.update_rows {
+                receiving.reported = rows;
+                events.push(StreamEvent::Partial(receiving.decoded(), rows));
+            }
+            break;
+        }
+        events
+    }
+
+    //picture cut short by the end of the stream, if one was being received
+    pub fn finish(&mut self) -> Option<Decoded> {
+        let receiving = self.receiving.take()?;
+        let options = DecodeOptions {
+            mode: Some(receiving.mode),
+            ..self.options
+        };
+        self.searched = 0.0;
+        //the full decode adds slant correction, the picture drawn line by line stands in if it fails
+        Some(decode(&self.samples, self.sample_rate, &options).unwrap_or_else(|_| receiving.decoded()))
+    }
+
+    //forgets the first n samples
+    fn drain(&mut self, n: usize) {
+        let n = n.min(self.samples.len());
+        self.samples.drain(..n);
+        self.freq.drain(..n);
+        self.track.drain(n);
+        self.searched = (self.searched - n as f64).max(0.0);
+    }
+}
+
+impl Receiving {
+    //places every line the track now covers, returns whether there were any
+    fn place_lines(&mut self, freq: &[f32], track: &Track, sample_rate: u32, free_running: bool) -> bool {
+        let ms = sample_rate as f64 / 1000.0;
+        let period = self.layout.period_ms * ms;
+        let placed = self.starts.len();
+        while self.starts.len() < self.lines {
+            let line = self.starts.len();
+            let expected = self.starts.last().map_or(self.from + self.layout.lead_ms * ms, |last| last + period);
+            let start = match self.layout.sync {
+                Some((sync_offset_ms, sync_ms)) if !free_running => {
+                    //the sync is looked for as in track_lines, once all of the audio it could be in is here
+                    let tolerance = (sync_ms * 0.5).max(1.0) * ms;
+                    let target = expected + (sync_offset_ms + sync_ms) * ms;
+                    if target.max(expected + period) + tolerance >= track.len() as f64 {
+                        break;
+                    }
+                    //a window back far enough for a sync merged with the VIS stop bit to still count as one
+                    let lo = (target - tolerance - 2.0 * sync_ms * ms).max(0.0) as usize;
+                    let hi = (target + tolerance) as usize + 1;
+                    let ends: Vec<f64> = sync_runs(&freq[lo..hi], &self.mode, (sync_ms * 0.5 * ms) as usize)
+                        .iter()
+                        .map(|r| (lo + r.1) as f64)
+                        .collect();
+                    nearest(&ends, target, tolerance).map_or(expected, |end| end - (sync_offset_ms + sync_ms) * ms)
+                }
+                _ if expected + period >= track.len() as f64 => break,
+                //AVT has no syncs, its first line is found from the counted down header
+                None if line == 0 => find_avt_start(track, sample_rate, self.from).unwrap_or(expected),
+                _ => expected,
+            };
+            self.picture.line(track, sample_rate, &self.layout, line, start);
+            self.starts.push(start);
+        }
+        self.starts.len() > placed
+    }
+
+    fn decoded(&self) -> Decoded {
+        Decoded {
+            mode: self.mode,
+            image: self.picture.image(&self.layout),
+            clock_error_ppm: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Encoder, EncoderOptions};
+
+    const SAMPLE_RATE: u32 = 11025;
+
+    //smooth colour ramps with a few flat blocks, so half-rate chroma still scores well
+    fn test_image(mode: &SSTVMode) -> image::RgbImage {
+        let (width, height) = mode.resolution();
+        image::RgbImage::from_fn(width, height, |x, y| {
+            let (u, v) = (x as f32 / width as f32, y as f32 / height as f32);
+            if (u * 4.0) as u32 % 2 == 1 && (v * 4.0) as u32 % 2 == 1 {
+                return image::Rgb([200, 60, 40]);
+            }
+            let wave = ((u * 3.0 + v * 2.0) * std::f32::consts::PI).sin() * 0.5 + 0.5;
+            image::Rgb([(u * 255.0) as u8, (v * 255.0) as u8, (wave * 255.0) as u8])
+        })
+    }
+
+    fn encode(mode: &SSTVMode, image: &image::RgbImage) -> Vec<f32> {
+        let encoder = Encoder::new(*mode, EncoderOptions {
+            sample_rate: SAMPLE_RATE,
+            ..EncoderOptions::default()
+        }).unwrap();
+        let mut cursor = std::io::Cursor::new(Vec::new());
+        encoder.encode_wav(image, &mut cursor).unwrap();
+
+        cursor.set_position(0);
+        hound::WavReader::new(cursor)
+            .unwrap()
+            .samples::<i16>()
+            .map(|s| s.unwrap() as f32 / 32768.0)
+            .collect()
+    }
+
+    //mono modes are compared against the luminance of the source
+    fn psnr(mode: &SSTVMode, source: &image::RgbImage, decoded: &image::RgbImage) -> f64 {
+        let mono = layout(mode).scans.iter().all(|scan| scan.channel == Channel::Luma);
+        let mut error = 0.0;
+        for (a, b) in source.pixels().zip(decoded.pixels()) {
+            let a = if mono {
+                let y = (crate::rgb_to_ycrcb(a[0], a[1], a[2]).0 * 255.0).round() as u8;
+                image::Rgb([y, y, y])
+            }
+            else {
+                *a
+            };
+            for c in 0..3 {
+                error += (a[c] as f64 - b[c] as f64).powi(2);
+            }
+        }
+        let mse = error / (source.len() as f64);
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+
+    //lowest acceptable quality, the short Robot lines carry chroma for every other row only
+    fn min_psnr(mode: &SSTVMode) -> f64 {
+        match mode {
+            SSTVMode::R12 | SSTVMode::R36 => 23.0,
+            _ => 30.0,
+        }
+    }
+
+    fn round_trip(mode: SSTVMode) -> Result<(), String> {
+        let source = test_image(&mode);
+        let samples = encode(&mode, &source);
+        let options = DecodeOptions {
+            mode: None,
+            slant_correction: true,
+            free_running: false,
+            correlate: true,
+        };
+        let decoded = decode(&samples, SAMPLE_RATE, &options).map_err(|e| format!("{:?}: {}", mode, e))?;
+        if decoded.mode != mode {
+            return Err(format!("{:?}: VIS decoded as {:?}", mode, decoded.mode));
+        }
+        if decoded.image.dimensions() != mode.resolution() {
+            return Err(format!("{:?}: decoded {:?}, expected {:?}", mode, decoded.image.dimensions(), mode.resolution()));
+        }
+        let psnr = psnr(&mode, &source, &decoded.image);
+        if psnr < min_psnr(&mode) {
+            return Err(format!("{:?}: PSNR {:.1} dB below {:.1} dB", mode, psnr, min_psnr(&mode)));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip_all_modes() {
+        let failures: Vec<String> = SSTVMode::ALL
+            .iter()
+            .filter_map(|&mode| round_trip(mode).err())
+            .collect();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    //leaders, break and start bit, the given bits LSB first, stop bit and a little picture tone
+    fn vis_header(bits: &[u8]) -> Vec<f32> {
+        let mut tones = vec![(1900.0, 300.0), (1200.0, 10.0), (1900.0, 300.0), (1200.0, 30.0)];
+        tones.extend(bits.iter().map(|&bit| (if bit == 1 {1100.0} else {1300.0}, 30.0)));
+        tones.extend([(1200.0, 30.0), (1500.0, 300.0)]);
+        let mut phase = 0.0f64;
+        let mut samples = Vec::new();
+        for (hz, ms) in tones {
+            for _ in 0..(ms * 11.025) as usize {
+                samples.push(phase.sin() as f32 * 0.5);
+                phase += 2.0 * std::f64::consts::PI * hz / 11025.0;
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn mmsstv_extended_vis() {
+        //MP73 as MMSSTV sends it: the 0x23 marker with its top bit clear, then the mode byte 0x25, no parity bits
+        let bits = [1, 1, 0, 0, 0, 1, 0, 0, 1, 0, 1, 0, 0, 1, 0, 0];
+        let track = Track::new(&demodulate(&vis_header(&bits), 11025));
+        let (mode, end) = detect_vis(&track, 11025).unwrap();
+        assert_eq!(mode, SSTVMode::MP73);
+        //leaders, break, start bit, 16 data bits and the stop bit
+        assert!((end / 11.025 - 1150.0).abs() < 2.0, "header ends at {:.1} ms", end / 11.025);
+    }
+
+    #[test]
+    fn standard_vis_parity() {
+        //Scottie 1 is 0x3C, four ones and a clear parity bit
+        let track = Track::new(&demodulate(&vis_header(&[0, 0, 1, 1, 1, 1, 0, 0]), 11025));
+        let (mode, end) = detect_vis(&track, 11025).unwrap();
+        assert_eq!(mode, SSTVMode::S1);
+        assert!((end / 11.025 - 910.0).abs() < 2.0, "header ends at {:.1} ms", end / 11.025);
+
+        let track = Track::new(&demodulate(&vis_header(&[0, 0, 1, 1, 1, 1, 0, 1]), 11025));
+        assert!(matches!(detect_vis(&track, 11025), Err(VisError::Parity(0x3C))));
+    }
+
+    #[test]
+    fn clock_error_is_measured_and_removed() {
+        let mode = SSTVMode::M2;
+        let source = test_image(&mode);
+        //a receiver clock 300 ppm fast records that many more samples
+        let samples = resample(&encode(&mode, &source), 1.0 / (1.0 + 300e-6));
+        let options = DecodeOptions {
+            mode: None,
+            slant_correction: true,
+            free_running: false,
+            correlate: true,
+        };
+        let decoded = decode(&samples, SAMPLE_RATE, &options).unwrap();
+        let ppm = decoded.clock_error_ppm.expect("no clock error reported");
+        assert!((ppm - 300.0).abs() < 20.0, "measured {:.1} ppm", ppm);
+        let psnr = psnr(&mode, &source, &decoded.image);
+        assert!(psnr >= 30.0, "PSNR {:.1} dB", psnr);
+    }
+
+    #[test]
+    fn free_running_without_vis_or_most_syncs() {
+        let mode = SSTVMode::S2;
+        let source = test_image(&mode);
+        let mut samples = encode(&mode, &source);
+        let layout = layout(&mode);
+        let ms = SAMPLE_RATE as f64 / 1000.0;
+        let (sync_offset_ms, sync_ms) = layout.sync.unwrap();
+        let lines = mode.resolution().1 as usize / layout.rows;
+
+        //the picture runs to the end of the audio
+        let first = samples.len() as f64 - (layout.lead_ms + lines as f64 * layout.period_ms) * ms;
+        //three syncs in four become mid grey
+        for line in (0..lines).filter(|line| line % 4 != 0) {
+            let start = (first + (layout.lead_ms + line as f64 * layout.period_ms + sync_offset_ms) * ms) as usize;
+            for (n, s) in samples[start..start + (sync_ms * ms) as usize].iter_mut().enumerate() {
+                *s = 0.5 * (2.0 * PI * 1900.0 * n as f64 / SAMPLE_RATE as f64).sin() as f32;
+            }
+        }
+        //and the recording starts just after the VIS header
+        let samples = &samples[(first - 100.0 * ms) as usize..];
+
+        let options = DecodeOptions {
+            mode: Some(mode),
+            slant_correction: true,
+            free_running: true,
+            //the diagonal ramps of the test image would read as a slant
+            correlate: false,
+        };
+        let decoded = decode(samples, SAMPLE_RATE, &options).unwrap();
+        let psnr = psnr(&mode, &source, &decoded.image);
+        assert!(psnr >= 30.0, "PSNR {:.1} dB", psnr);
+    }
+
+    #[test]
+    fn stream_in_blocks_matches_one_shot() {
+        let mode = SSTVMode::M2;
+        let source = test_image(&mode);
+        let samples = encode(&mode, &source);
+        let options = DecodeOptions {
+            mode: None,
+            slant_correction: true,
+            free_running: false,
+            correlate: true,
+        };
+        let one_shot = decode(&samples, SAMPLE_RATE, &options).unwrap();
+
+        //calibration tones and a header whose first data bit flipped from 1300 to 1100 Hz, then the real transmission and a pause
+        let ms = SAMPLE_RATE as usize / 1000;
+        let mut broken = samples[..1710 * ms].to_vec();
+        for (n, s) in broken[1440 * ms..1470 * ms].iter_mut().enumerate() {
+            *s = 0.5 * (2.0 * PI * 1100.0 * n as f64 / SAMPLE_RATE as f64).sin() as f32;
+        }
+        let stream: Vec<f32> = broken.into_iter().chain(samples.iter().copied()).chain(vec![0.0; 1000 * ms]).collect();
+
+        let mut decoder = StreamDecoder::new(SAMPLE_RATE, options, 32);
+        let mut events = Vec::new();
+        for block in stream.chunks(1000) {
+            events.extend(decoder.push(block));
+        }
+        assert!(decoder.finish().is_none());
+
+        let mut started = 0;
+        let mut rows = Vec::new();
+        let mut finished = Vec::new();
+        for event in events {
+            match event {
+                StreamEvent::Started(started_mode) => {
+                    assert_eq!(started_mode, mode);
+                    started += 1;
+                }
+                StreamEvent::Partial(_, received) => rows.push(received),
+                StreamEvent::Finished(decoded) => finished.push(decoded),
+            }
+        }
+        assert_eq!(started, 1);
+        assert!(rows.len() >= 6 && rows.windows(2).all(|w| w[0] < w[1]), "{:?}", rows);
+        assert_eq!(finished.len(), 1);
+        let similarity = psnr(&SSTVMode::M1, &one_shot.image, &finished[0].image);
+        assert!(similarity > 40.0, "stream and one-shot differ, PSNR {:.1} dB", similarity);
+    }
+
+    #[test]
+    fn silence_is_no_picture() {
+        let options = DecodeOptions {
+            mode: Some(SSTVMode::S1),
+            slant_correction: true,
+            free_running: false,
+            correlate: true,
+        };
+        assert!(matches!(decode(&vec![0.0; 22050], 11025, &options), Err(VisError::NoPicture)));
+    }
+}
@@ -0,0 +1,85 @@
+use crate::decode::VisError;
+
+#[derive(Debug)]
+pub enum Error {
+    //bad command line value, the message names the option
+    InvalidArgument(String),
+    UnknownMode(String),
+    InputImage(image::ImageError),
+    InputAudio(hound::Error),
+    //writing audio or an image failed, e.g. a full disk
+    Output(std::io::Error),
+    OutputImage(image::ImageError),
+    Decode(VisError),
+    Playback(String),
+    //unreadable template, or one that asks for something it wasn't given
+    Template(String),
+    //a mode layout produced a negative tone length
+    InvalidDuration(f32),
+}
+
+impl Error {
+    //process exit code for the CLI, each kind of failure gets its own
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::InvalidArgument(_) | Error::UnknownMode(_) => 2,
+            Error::InputImage(_) => 3,
+            Error::InputAudio(_) => 4,
+            Error::Output(_) | Error::OutputImage(_) => 5,
+            Error::Decode(_) => 6,
+            Error::Playback(_) => 7,
+            Error::Template(_) => 8,
+            Error::InvalidDuration(_) => 9,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidArgument(message) => write!(f, "{}", message),
+            Error::UnknownMode(name) => write!(f, "Unknown SSTV mode: {}", name),
+            Error::InputImage(e) => write!(f, "Failed to open image: {}", e),
+            Error::InputAudio(e) => write!(f, "Failed to read wav file: {}", e),
+            Error::Output(e) => write!(f, "Failed to write output: {}", e),
+            Error::OutputImage(e) => write!(f, "Failed to save image: {}", e),
+            Error::Decode(e) => write!(f, "{}", e),
+            Error::Playback(message) => write!(f, "Playback failed: {}", message),
+            Error::Template(message) => write!(f, "Template: {}", message),
+            Error::InvalidDuration(ms) => write!(f, "Invalid tone duration: {} ms", ms),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InputImage(e) | Error::OutputImage(e) => Some(e),
+            Error::InputAudio(e) => Some(e),
+            Error::Output(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Output(e)
+    }
+}
+
+//hound errors reaching the encoder come from writing
+impl From<hound::Error> for Error {
+    fn from(e: hound::Error) -> Self {
+        match e {
+            hound::Error::IoError(e) => Error::Output(e),
+            e => Error::Output(std::io::Error::other(e)),
+        }
+    }
+}
+
+impl From<VisError> for Error {
+    fn from(e: VisError) -> Self {
+        Error::Decode(e)
+    }
+}
@@ -0,0 +1,112 @@
+use image::{Rgb, RgbImage, imageops};
+
+//how an image whose aspect ratio differs from the mode's is brought to its resolution
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Fit {
+    //scales each axis on its own, distorting the picture
+    #[default]
+    Stretch,
+    //scales the whole picture to fit, the bars left over are filled with pad
+    Letterbox {pad: Rgb<u8>},
+    //scales to fill and cuts off what sticks out, keeping the focus point as central as the edges allow
+    //focus is a fraction of the image width and height, (0.5, 0.5) crops the centre
+    Crop {focus: (f32, f32)},
+}
+
+impl Fit {
+    pub fn apply(&self, image: &RgbImage, width: u32, height: u32, filter: imageops::FilterType) -> RgbImage {
+        let (w, h) = image.dimensions();
+        if (w, h) == (width, height) || w == 0 || h == 0 {
+            return imageops::resize(image, width, height, filter);
+        }
+        match *self {
+            Fit::Stretch => imageops::resize(image, width, height, filter),
+            Fit::Letterbox {pad} => {
+                let scale = (width as f64 / w as f64).min(height as f64 / h as f64);
+                let scaled_w = ((w as f64 * scale).round() as u32).clamp(1, width);
+                let scaled_h = ((h as f64 * scale).round() as u32).clamp(1, height);
+                let scaled = imageops::resize(image, scaled_w, scaled_h, filter);
+                let mut canvas = RgbImage::from_pixel(width, height, pad);
+                imageops::replace(&mut canvas, &scaled, ((width - scaled_w) / 2) as i64, ((height - scaled_h) / 2) as i64);
+                canvas
+            }
+            Fit::Crop {focus} => {
+                //the part of the source that covers the target once scaled
+                let scale = (width as f64 / w as f64).max(height as f64 / h as f64);
+                let crop_w = ((width as f64 / scale).round() as u32).clamp(1, w);
+                let crop_h = ((height as f64 / scale).round() as u32).clamp(1, h);
+                let left = (focus.0.clamp(0.0, 1.0) as f64 * w as f64 - crop_w as f64 / 2.0).round().clamp(0.0, (w - crop_w) as f64) as u32;
+                let top = (focus.1.clamp(0.0, 1.0) as f64 * h as f64 - crop_h as f64 / 2.0).round().clamp(0.0, (h - crop_h) as f64) as u32;
+                let cropped = imageops::crop_imm(image, left, top, crop_w, crop_h).to_image();
+                imageops::resize(&cropped, width, height, filter)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgb<u8> = Rgb([255, 0, 0]);
+    const GREEN: Rgb<u8> = Rgb([0, 255, 0]);
+    const BLUE: Rgb<u8> = Rgb([0, 0, 255]);
+
+    fn apply(fit: Fit, image: &RgbImage) -> RgbImage {
+        fit.apply(image, 100, 100, imageops::FilterType::Nearest)
+    }
+
+    #[test]
+    fn stretch_fills_the_target() {
+        let image = RgbImage::from_pixel(300, 40, RED);
+        let fitted = apply(Fit::Stretch, &image);
+        assert_eq!(fitted.dimensions(), (100, 100));
+        assert!(fitted.pixels().all(|&p| p == RED));
+    }
+
+    #[test]
+    fn letterbox_pads_top_and_bottom() {
+        let image = RgbImage::from_pixel(200, 100, RED);
+        let fitted = apply(Fit::Letterbox {pad: BLUE}, &image);
+        assert_eq!(fitted.dimensions(), (100, 100));
+        //scaled to 100x50 and centred
+        for y in 0..100 {
+            let expected = if (25..75).contains(&y) {RED} else {BLUE};
+            assert_eq!(*fitted.get_pixel(0, y), expected, "row {}", y);
+            assert_eq!(*fitted.get_pixel(99, y), expected, "row {}", y);
+        }
+    }
+
+    #[test]
+    fn letterbox_pads_left_and_right() {
+        let image = RgbImage::from_pixel(50, 200, RED);
+        let fitted = apply(Fit::Letterbox {pad: GREEN}, &image);
+        //scaled to 25x100, odd leftovers go to the right
+        for x in 0..100 {
+            let expected = if (37..62).contains(&x) {RED} else {GREEN};
+            assert_eq!(*fitted.get_pixel(x, 50), expected, "column {}", x);
+        }
+    }
+
+    #[test]
+    fn crop_follows_the_focus() {
+        //thirds of red, green and blue, each third is exactly what a 100x100 crop keeps
+        let image = RgbImage::from_fn(300, 100, |x, _| [RED, GREEN, BLUE][x as usize / 100]);
+        for (focus, expected) in [((0.5, 0.5), GREEN), ((0.0, 0.5), RED), ((1.0, 0.5), BLUE), ((0.1, 0.0), RED)] {
+            let fitted = apply(Fit::Crop {focus}, &image);
+            assert_eq!(fitted.dimensions(), (100, 100));
+            assert!(fitted.pixels().all(|&p| p == expected), "focus {:?}", focus);
+        }
+        //centred on x 120, the crop runs from 70 to 170
+        let fitted = apply(Fit::Crop {focus: (0.4, 0.5)}, &image);
+        assert_eq!(*fitted.get_pixel(29, 50), RED);
+        assert_eq!(*fitted.get_pixel(30, 50), GREEN);
+    }
+
+    #[test]
+    fn crop_clamps_the_focus_to_the_edges() {
+        let image = RgbImage::from_fn(100, 300, |_, y| [RED, GREEN, BLUE][y as usize / 100]);
+        assert!(apply(Fit::Crop {focus: (0.5, 0.9)}, &image).pixels().all(|&p| p == BLUE));
+        assert!(apply(Fit::Crop {focus: (0.5, -1.0)}, &image).pixels().all(|&p| p == RED));
+    }
+}
@@ -0,0 +1,274 @@
+use crate::SSTVMode;
+
+//AVT digital header, sent instead of line syncs
+pub const AVT_HEADER_BIT_MS: f64 = 1000.0 / 102.4;
+const AVT_HEADER_BIT_1_HZ: f64 = 1900.0;
+const AVT_HEADER_BIT_0_HZ: f64 = 1500.0;
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Luma,
+    RedDiff,
+    BlueDiff,
+    //R-Y on even lines, B-Y on odd lines
+    AltDiff,
+}
+
+//frequency of a porch or separator
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Tone {
+    //the mode's black level
+    Black,
+    Hz(f64),
+    //one frequency on even lines and another on odd lines
+    Alternate(f64, f64),
+}
+
+impl Tone {
+    pub fn hz(&self, mode: &SSTVMode, line: usize) -> f64 {
+        match *self {
+            Tone::Black => mode.frequency_range().0 as f64,
+            Tone::Hz(hz) => hz,
+            Tone::Alternate(even, odd) => if line.is_multiple_of(2) {even} else {odd},
+        }
+    }
+}
+
+pub struct Scan {
+    pub channel: Channel,
+    //image row within the line, None when the scan is shared by every row
+    pub row: Option<usize>,
+    pub start_ms: f64,
+    pub duration_ms: f64,
+    //pixels in the scan, fewer than the image width averages neighbouring pixels
+    pub samples: usize,
+}
+
+pub enum Segment {
+    Sync(f64),
+    Porch(Tone, f64),
+    //index into Layout::scans
+    Scan(usize),
+}
+
+//timing of one transmitted line, from the start of its first tone to the start of the next line
+pub struct Layout {
+    //image rows carried by one line
+    pub rows: usize,
+    pub period_ms: f64,
+    //offset and length of the line sync
+    pub sync: Option<(f64, f64)>,
+    pub scans: Vec<Scan>,
+    pub segments: Vec<Segment>,
+    //tones sent once before the first line, and their length
+    pub lead: Vec<Segment>,
+    pub lead_ms: f64,
+}
+
+impl Layout {
+    fn new(rows: usize) -> Self {
+        Self {
+            rows,
+            period_ms: 0.0,
+            sync: None,
+            scans: Vec::new(),
+            segments: Vec::new(),
+            lead: Vec::new(),
+            lead_ms: 0.0,
+        }
+    }
+    fn sync(&mut self, ms: f64) {
+        self.sync = Some((self.period_ms, ms));
+        self.segments.push(Segment::Sync(ms));
+        self.period_ms += ms;
+    }
+    fn porch(&mut self, tone: Tone, ms: f64) {
+        self.segments.push(Segment::Porch(tone, ms));
+        self.period_ms += ms;
+    }
+    fn scan(&mut self, channel: Channel, row: Option<usize>, ms: f64, samples: usize) {
+        self.segments.push(Segment::Scan(self.scans.len()));
+        self.scans.push(Scan {
+            channel,
+            row,
+            start_ms: self.period_ms,
+            duration_ms: ms,
+            samples,
+        });
+        self.period_ms += ms;
+    }
+    fn lead(&mut self, segment: Segment) {
+        self.lead_ms += match segment {
+            Segment::Sync(ms) | Segment::Porch(_, ms) => ms,
+            Segment::Scan(_) => 0.0,
+        };
+        self.lead.push(segment);
+    }
+}
+
+//line structure of every mode, walked by SSTVMode::write_segment and the decoder alike
+pub fn layout(mode: &SSTVMode) -> Layout {
+    let width = mode.resolution().0 as usize;
+    let scan_ms = mode.color_scanline_ms() as f64;
+    match mode {
+        SSTVMode::M1 | SSTVMode::M2 | SSTVMode::M3 | SSTVMode::M4 => {
+            let mut l = Layout::new(1);
+            l.sync(4.862);
+            l.porch(Tone::Black, 0.572);
+            l.scan(Channel::Green, Some(0), scan_ms, width);
+            l.porch(Tone::Black, 0.572);
+            l.scan(Channel::Blue, Some(0), scan_ms, width);
+            l.porch(Tone::Black, 0.572);
+            l.scan(Channel::Red, Some(0), scan_ms, width);
+            l.porch(Tone::Black, 0.572);
+            l
+        }
+        SSTVMode::S1 | SSTVMode::S2 | SSTVMode::S3 | SSTVMode::S4 | SSTVMode::SDX => {
+            //the sync sits mid line, a single extra one starts the first line
+            let mut l = Layout::new(1);
+            l.lead(Segment::Sync(9.0));
+            l.porch(Tone::Black, 1.5);
+            l.scan(Channel::Green, Some(0), scan_ms, width);
+            l.porch(Tone::Black, 1.5);
+            l.scan(Channel::Blue, Some(0), scan_ms, width);
+            l.sync(9.0);
+            l.porch(Tone::Black, 1.5);
+            l.scan(Channel::Red, Some(0), scan_ms, width);
+            l
+        }
+        SSTVMode::R12 | SSTVMode::R36 => {
+            //one colour difference per line, the separator tells R-Y from B-Y
+            let (line_sync_ms, separator_ms, y_scan_ms, color_scan_ms) = match mode {
+                SSTVMode::R12 => (7.0, 3.0, 60.0, 30.0),
+                _ => (9.0, 4.5, 88.0, 44.0),
+            };
+            let mut l = Layout::new(1);
+            l.sync(line_sync_ms);
+            l.porch(Tone::Hz(1500.0), 3.0);
+            l.scan(Channel::Luma, Some(0), y_scan_ms, width);
+            l.porch(Tone::Alternate(1500.0, 2300.0), separator_ms);
+            l.porch(Tone::Hz(1900.0), 1.5);
+            l.scan(Channel::AltDiff, Some(0), color_scan_ms, width);
+            l
+        }
+        SSTVMode::R24 | SSTVMode::R72 => {
+            let (y_scan_ms, color_scan_ms) = match mode {
+                SSTVMode::R24 => (88.0, 44.0),
+                _ => (138.0, 69.0),
+            };
+            let mut l = Layout::new(1);
+            l.sync(9.0);
+            l.porch(Tone::Hz(1500.0), 3.0);
+            l.scan(Channel::Luma, Some(0), y_scan_ms, width);
+            l.porch(Tone::Hz(1500.0), 4.5);
+            l.porch(Tone::Hz(1900.0), 1.5);
+            l.scan(Channel::RedDiff, Some(0), color_scan_ms, width);
+            l.porch(Tone::Hz(2300.0), 4.5);
+            l.porch(Tone::Hz(1900.0), 1.5);
+            l.scan(Channel::BlueDiff, Some(0), color_scan_ms, width);
+            l
+        }
+        SSTVMode::PD50 | SSTVMode::PD90 | SSTVMode::PD120 | SSTVMode::PD160 | SSTVMode::PD180 | SSTVMode::PD240 | SSTVMode::PD290 |
+        SSTVMode::MP73 | SSTVMode::MP115 | SSTVMode::MP140 | SSTVMode::MP175 |
+        SSTVMode::MN73 | SSTVMode::MN110 | SSTVMode::MN140 => {
+            let (line_sync_ms, porch_ms) = match mode {
+                SSTVMode::MP73 | SSTVMode::MP115 | SSTVMode::MP140 | SSTVMode::MP175 |
+                SSTVMode::MN73 | SSTVMode::MN110 | SSTVMode::MN140 => (9.0, 1.0),
+                _ => (20.0, 2.08),
+            };
+            //one sync carries two image rows: Y of the even row, R-Y and B-Y shared by both, Y of the odd row
+            let mut l = Layout::new(2);
+            l.sync(line_sync_ms);
+            l.porch(Tone::Black, porch_ms);
+            l.scan(Channel::Luma, Some(0), scan_ms, width);
+            l.scan(Channel::RedDiff, None, scan_ms, width);
+            l.scan(Channel::BlueDiff, None, scan_ms, width);
+            l.scan(Channel::Luma, Some(1), scan_ms, width);
+            l
+        }
+        SSTVMode::SC2_30 | SSTVMode::SC2_60 | SSTVMode::SC2_120 | SSTVMode::SC2_180 |
+        SSTVMode::MC110 | SSTVMode::MC140 | SSTVMode::MC180 => {
+            let line_sync_ms = match mode {
+                SSTVMode::MC110 | SSTVMode::MC140 | SSTVMode::MC180 => 8.0,
+                _ => 5.5225,
+            };
+            let mut l = Layout::new(1);
+            l.sync(line_sync_ms);
+            l.porch(Tone::Black, 0.5);
+            l.scan(Channel::Red, Some(0), scan_ms, width);
+            l.scan(Channel::Green, Some(0), scan_ms, width);
+            l.scan(Channel::Blue, Some(0), scan_ms, width);
+            l
+        }
+        SSTVMode::P3 | SSTVMode::P5 | SSTVMode::P7 => {
+            //timings are whole multiples of the mode's time unit: sync 25 units, porch 5 units
+            let unit_ms = match mode {
+                SSTVMode::P3 => 1000.0 / 4800.0,
+                SSTVMode::P5 => 1000.0 / 3200.0,
+                _ => 1000.0 / 2400.0,
+            };
+            //the scans too, one unit per pixel rather than the rounded scanline time
+            let scan_ms = width as f64 * unit_ms;
+            let mut l = Layout::new(1);
+            l.sync(25.0 * unit_ms);
+            l.porch(Tone::Black, 5.0 * unit_ms);
+            l.scan(Channel::Red, Some(0), scan_ms, width);
+            l.porch(Tone::Black, 5.0 * unit_ms);
+            l.scan(Channel::Green, Some(0), scan_ms, width);
+            l.porch(Tone::Black, 5.0 * unit_ms);
+            l.scan(Channel::Blue, Some(0), scan_ms, width);
+            l.porch(Tone::Black, 5.0 * unit_ms);
+            l
+        }
+        SSTVMode::MR73 | SSTVMode::MR90 | SSTVMode::MR115 | SSTVMode::MR140 | SSTVMode::MR175 |
+        SSTVMode::ML180 | SSTVMode::ML240 | SSTVMode::ML280 | SSTVMode::ML320 => {
+            //YC 4:2:2, chrominance carries one sample per two luminance pixels
+            let mut l = Layout::new(1);
+            l.sync(9.0);
+            l.porch(Tone::Black, 1.0);
+            l.scan(Channel::Luma, Some(0), scan_ms, width);
+            l.porch(Tone::Black, 0.1);
+            l.scan(Channel::RedDiff, Some(0), scan_ms / 2.0, width / 2);
+            l.porch(Tone::Black, 0.1);
+            l.scan(Channel::BlueDiff, Some(0), scan_ms / 2.0, width / 2);
+            l.porch(Tone::Black, 0.1);
+            l
+        }
+        SSTVMode::BW8 | SSTVMode::BW12 | SSTVMode::BW24 | SSTVMode::BW36 | SSTVMode::FAX480 => {
+            let line_sync_ms = match mode {
+                SSTVMode::BW36 => 12.0,
+                SSTVMode::FAX480 => 5.12,
+                _ => 7.0,
+            };
+            let mut l = Layout::new(1);
+            l.sync(line_sync_ms);
+            l.scan(Channel::Luma, Some(0), scan_ms, width);
+            l
+        }
+        SSTVMode::AVT24 | SSTVMode::AVT90 | SSTVMode::AVT94 | SSTVMode::AVT125 => {
+            let mut l = Layout::new(1);
+            //digital header, AVT has no line sync so the receiver locks onto this instead
+            //32 words counting down to 0, each the count byte followed by its complement, LSB first
+            for count in (0..32u8).rev() {
+                let word: u16 = (count as u16) | ((!count as u16) << 8);
+                for bit in 0..16 {
+                    let hz = if (word >> bit) & 1 == 1 {AVT_HEADER_BIT_1_HZ} else {AVT_HEADER_BIT_0_HZ};
+                    l.lead(Segment::Porch(Tone::Hz(hz), AVT_HEADER_BIT_MS));
+                }
+            }
+            if *mode == SSTVMode::AVT125 {
+                l.scan(Channel::Luma, Some(0), scan_ms, width);
+            }
+            else {
+                l.scan(Channel::Red, Some(0), scan_ms, width);
+                l.scan(Channel::Green, Some(0), scan_ms, width);
+                l.scan(Channel::Blue, Some(0), scan_ms, width);
+            }
+            l
+        }
+    }
+}
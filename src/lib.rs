@@ -0,0 +1,830 @@
+use std::str::FromStr;
+use std::f32::consts::PI;
+
+pub mod channel;
+pub mod decode;
+pub mod error;
+pub mod fit;
+mod layout;
+pub mod overlay;
+pub mod pattern;
+pub mod sink;
+pub mod template;
+
+use sink::SampleSink;
+pub use error::Error;
+pub use fit::Fit;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum SSTVMode {
+    R12,
+    R24,
+    R36,
+    R72,
+    M1,
+    M2,
+    M3,
+    M4,
+    S1,
+    S2,
+    S3,
+    S4,
+    SDX,
+    PD50,
+    PD90,
+    PD120,
+    PD160,
+    PD180,
+    PD240,
+    PD290,
+    SC2_30,
+    SC2_60,
+    SC2_120,
+    SC2_180,
+    P3,
+    P5,
+    P7,
+    MP73,
+    MP115,
+    MP140,
+    MP175,
+    MR73,
+    MR90,
+    MR115,
+    MR140,
+    MR175,
+    ML180,
+    ML240,
+    ML280,
+    ML320,
+    MN73,
+    MN110,
+    MN140,
+    MC110,
+    MC140,
+    MC180,
+    BW8,
+    BW12,
+    BW24,
+    BW36,
+    AVT24,
+    AVT90,
+    AVT94,
+    AVT125,
+    FAX480,
+}
+
+impl SSTVMode {
+    pub const ALL: [SSTVMode; 55] = [
+        SSTVMode::R12, SSTVMode::R24, SSTVMode::R36, SSTVMode::R72, SSTVMode::M1, SSTVMode::M2,
+        SSTVMode::M3, SSTVMode::M4, SSTVMode::S1, SSTVMode::S2, SSTVMode::S3, SSTVMode::S4,
+        SSTVMode::SDX, SSTVMode::PD50, SSTVMode::PD90, SSTVMode::PD120, SSTVMode::PD160,
+        SSTVMode::PD180, SSTVMode::PD240, SSTVMode::PD290, SSTVMode::SC2_30, SSTVMode::SC2_60,
+        SSTVMode::SC2_120, SSTVMode::SC2_180, SSTVMode::P3, SSTVMode::P5, SSTVMode::P7,
+        SSTVMode::MP73, SSTVMode::MP115, SSTVMode::MP140, SSTVMode::MP175, SSTVMode::MR73,
+        SSTVMode::MR90, SSTVMode::MR115, SSTVMode::MR140, SSTVMode::MR175, SSTVMode::ML180,
+        SSTVMode::ML240, SSTVMode::ML280, SSTVMode::ML320, SSTVMode::MN73, SSTVMode::MN110,
+        SSTVMode::MN140, SSTVMode::MC110, SSTVMode::MC140, SSTVMode::MC180, SSTVMode::BW8,
+        SSTVMode::BW12, SSTVMode::BW24, SSTVMode::BW36, SSTVMode::AVT24, SSTVMode::AVT90,
+        SSTVMode::AVT94, SSTVMode::AVT125, SSTVMode::FAX480,
+    ];
+
+    pub fn resolution(&self) -> (u32, u32) {
+        match self {
+            SSTVMode::R12 => (160, 120),
+            SSTVMode::R24 => (160, 120),
+            SSTVMode::R36 => (320, 240),
+            SSTVMode::R72 => (320, 240),
+            SSTVMode::M1 => (320, 256),
+            SSTVMode::M2 => (160, 256),
+            SSTVMode::M3 => (320, 128),
+            SSTVMode::M4 => (160, 128),
+            SSTVMode::S1 => (320, 256),
+            SSTVMode::S2 => (160, 256),
+            SSTVMode::S3 => (320, 128),
+            SSTVMode::S4 => (160, 128),
+            SSTVMode::SDX => (320, 256),
+            SSTVMode::PD50 => (320, 256),
+            SSTVMode::PD90 => (320, 256),
+            SSTVMode::PD120 => (640, 496),
+            SSTVMode::PD160 => (512, 400),
+            SSTVMode::PD180 => (640, 496),
+            SSTVMode::PD240 => (640, 496),
+            SSTVMode::PD290 => (800, 616),
+            SSTVMode::SC2_30 => (320, 128),
+            SSTVMode::SC2_60 => (320, 256),
+            SSTVMode::SC2_120 => (320, 256),
+            SSTVMode::SC2_180 => (320, 256),
+            SSTVMode::P3 => (640, 496),
+            SSTVMode::P5 => (640, 496),
+            SSTVMode::P7 => (640, 496),
+            SSTVMode::MP73 | SSTVMode::MP115 | SSTVMode::MP140 | SSTVMode::MP175 => (320, 256),
+            SSTVMode::MR73 | SSTVMode::MR90 | SSTVMode::MR115 | SSTVMode::MR140 | SSTVMode::MR175 => (320, 256),
+            SSTVMode::ML180 | SSTVMode::ML240 | SSTVMode::ML280 | SSTVMode::ML320 => (640, 496),
+            SSTVMode::MN73 | SSTVMode::MN110 | SSTVMode::MN140 => (320, 256),
+            SSTVMode::MC110 | SSTVMode::MC140 | SSTVMode::MC180 => (320, 256),
+            SSTVMode::BW8 => (160, 120),
+            SSTVMode::BW12 => (160, 120),
+            SSTVMode::BW24 => (320, 240),
+            SSTVMode::BW36 => (320, 240),
+            SSTVMode::AVT24 => (128, 120),
+            SSTVMode::AVT90 => (256, 240),
+            SSTVMode::AVT94 => (320, 200),
+            SSTVMode::AVT125 => (320, 400),
+            SSTVMode::FAX480 => (512, 480),
+        }
+    }
+    //codes above 0x7F are MMSSTV 16-bit extended VIS: low byte 0x23, high byte the mode
+    pub fn vis_code(&self) -> u16 {
+        match self {
+            SSTVMode::R12 => 0b0000000,
+            SSTVMode::R24 => 0b0000100,
+            SSTVMode::R36 => 0b0001000,
+            SSTVMode::R72 => 0b0001100,
+            SSTVMode::M1 => 0b0101100,
+            SSTVMode::M2 => 0b0101000,
+            SSTVMode::M3 => 0b0100100,
+            SSTVMode::M4 => 0b0100000,
+            SSTVMode::S1 => 0b0111100,
+            SSTVMode::S2 => 0b0111000,
+            SSTVMode::S3 => 0b0110100,
+            SSTVMode::S4 => 0b0110000,
+            SSTVMode::SDX => 0b1001100,
+            SSTVMode::PD50 => 0b1011101,
+            SSTVMode::PD90 => 0b1100011,
+            SSTVMode::PD120 => 0b1011111,
+            SSTVMode::PD160 => 0b1100010,
+            SSTVMode::PD180 => 0b1100000,
+            SSTVMode::PD240 => 0b1100001,
+            SSTVMode::PD290 => 0b1011110,
+            SSTVMode::SC2_30 => 0b0110011,
+            SSTVMode::SC2_60 => 0b0111011,
+            SSTVMode::SC2_120 => 0b0111111,
+            SSTVMode::SC2_180 => 0b0110111,
+            SSTVMode::P3 => 0b1110001,
+            SSTVMode::P5 => 0b1110010,
+            SSTVMode::P7 => 0b1110011,
+            SSTVMode::MP73 => 0x2523,
+            SSTVMode::MP115 => 0x2923,
+            SSTVMode::MP140 => 0x2A23,
+            SSTVMode::MP175 => 0x2C23,
+            SSTVMode::MR73 => 0x4523,
+            SSTVMode::MR90 => 0x4623,
+            SSTVMode::MR115 => 0x4923,
+            SSTVMode::MR140 => 0x4A23,
+            SSTVMode::MR175 => 0x4C23,
+            SSTVMode::ML180 => 0x8523,
+            SSTVMode::ML240 => 0x8623,
+            SSTVMode::ML280 => 0x8923,
+            SSTVMode::ML320 => 0x8A23,
+            SSTVMode::MN73 => 0x0223,
+            SSTVMode::MN110 => 0x0423,
+            SSTVMode::MN140 => 0x0523,
+            SSTVMode::MC110 => 0x1423,
+            SSTVMode::MC140 => 0x1523,
+            SSTVMode::MC180 => 0x1623,
+            SSTVMode::BW8 => 0b0000010,
+            SSTVMode::BW12 => 0b0000110,
+            SSTVMode::BW24 => 0b0001010,
+            SSTVMode::BW36 => 0b0001110,
+            SSTVMode::AVT24 => 0b1000000,
+            SSTVMode::AVT90 => 0b1000100,
+            SSTVMode::AVT94 => 0b1001000,
+            SSTVMode::AVT125 => 0b1010100,
+            SSTVMode::FAX480 => 0b1010101,
+        }
+    }
+    fn color_scanline_ms(&self) -> f32 {
+        match self {
+            SSTVMode::R12 | SSTVMode::R24 | SSTVMode::R36 | SSTVMode::R72 => 0.0,
+            SSTVMode::M1 => 146.432,
+            SSTVMode::M2 => 73.216,
+            SSTVMode::M3 => 146.432,
+            SSTVMode::M4 => 73.216,
+            SSTVMode::S1 => 138.240,
+            SSTVMode::S2 => 88.064,
+            SSTVMode::S3 => 138.240,
+            SSTVMode::S4 => 88.064,
+            SSTVMode::SDX => 345.600,
+            SSTVMode::PD50 => 91.520,
+            SSTVMode::PD90 => 170.240,
+            SSTVMode::PD120 => 121.600,
+            SSTVMode::PD160 => 195.584,
+            SSTVMode::PD180 => 183.040,
+            SSTVMode::PD240 => 244.480,
+            SSTVMode::PD290 => 228.800,
+            SSTVMode::SC2_30 => 78.000,
+            SSTVMode::SC2_60 => 78.000,
+            SSTVMode::SC2_120 => 156.500,
+            SSTVMode::SC2_180 => 235.000,
+            SSTVMode::P3 => 133.333,
+            SSTVMode::P5 => 200.000,
+            SSTVMode::P7 => 266.667,
+            SSTVMode::MP73 => 140.000,
+            SSTVMode::MP115 => 222.500,
+            SSTVMode::MP140 => 277.500,
+            SSTVMode::MP175 => 347.500,
+            SSTVMode::MR73 => 138.000,
+            SSTVMode::MR90 => 171.800,
+            SSTVMode::MR115 => 220.000,
+            SSTVMode::MR140 => 269.000,
+            SSTVMode::MR175 => 337.000,
+            SSTVMode::ML180 => 176.500,
+            SSTVMode::ML240 => 236.500,
+            SSTVMode::ML280 => 277.500,
+            SSTVMode::ML320 => 317.500,
+            SSTVMode::MN73 => 140.000,
+            SSTVMode::MN110 => 212.000,
+            SSTVMode::MN140 => 271.500,
+            SSTVMode::MC110 => 140.000,
+            SSTVMode::MC140 => 180.000,
+            SSTVMode::MC180 => 232.000,
+            SSTVMode::BW8 => 60.000,
+            SSTVMode::BW12 => 93.000,
+            SSTVMode::BW24 => 93.000,
+            SSTVMode::BW36 => 138.000,
+            SSTVMode::AVT24 => 62.500,
+            SSTVMode::AVT90 => 125.000,
+            SSTVMode::AVT94 => 156.250,
+            SSTVMode::AVT125 => 312.500,
+            SSTVMode::FAX480 => 262.144,
+        }
+    }
+    //reverse of vis_code()
+    pub fn from_vis_code(code: u16) -> Option<SSTVMode> {
+        SSTVMode::ALL.into_iter().find(|mode| mode.vis_code() == code)
+    }
+    //narrowband modes keep sync and picture inside 1900-2300 Hz
+    pub fn is_narrow(&self) -> bool {
+        matches!(self,
+            SSTVMode::MN73 | SSTVMode::MN110 | SSTVMode::MN140 |
+            SSTVMode::MC110 | SSTVMode::MC140 | SSTVMode::MC180
+        )
+    }
+    pub fn sync_hz(&self) -> f32 {
+        if self.is_narrow() {1900.0} else {1200.0}
+    }
+    //black and white frequencies
+    pub fn frequency_range(&self) -> (f32, f32) {
+        if self.is_narrow() {(2044.0, 2300.0)} else {(1500.0, 2300.0)}
+    }
+    fn luminance_freq(&self, value: f32) -> f32 {
+        let (black, white) = self.frequency_range();
+        black + (white - black) * value
+    }
+    //chrominance is centred between black and white, -1.0..1.0 spans the whole range
+    fn chrominance_freq(&self, value: f32) -> f32 {
+        let (black, white) = self.frequency_range();
+        (black + white) / 2.0 + (white - black) / 2.0 * value
+    }
+    //one sync, porch or scan of a line, every tone comes from layout::layout()
+    fn write_segment<S: SampleSink + ?Sized>(
+        &self,
+        sink: &mut S,
+        osc: &mut Oscillator,
+        image: &image::ImageBuffer<image::Rgb<u8>, Vec<u8>>,
+        layout: &layout::Layout,
+        segment: &layout::Segment,
+        line: usize
+        ) -> Result<(), Error> {
+        match segment {
+            layout::Segment::Sync(ms) => emit_tone(sink, osc, self.sync_hz(), *ms as f32),
+            layout::Segment::Porch(tone, ms) => emit_tone(sink, osc, tone.hz(self, line) as f32, *ms as f32),
+            layout::Segment::Scan(index) => {
+                let scan = &layout.scans[*index];
+                let width = self.resolution().0 as usize;
+
+                //a scan shared by the rows of the line carries their average, likewise for pixels sharing a sample
+                let rows: Vec<usize> = match scan.row {
+                    Some(r) => vec![line * layout.rows + r],
+                    None => (0..layout.rows).map(|r| line * layout.rows + r).collect(),
+                };
+                let span = width / scan.samples;
+                let pixel_ms = scan.duration_ms / scan.samples as f64;
+                let channel = match scan.channel {
+                    layout::Channel::AltDiff => if (line * layout.rows).is_multiple_of(2) {layout::Channel::RedDiff} else {layout::Channel::BlueDiff},
+                    c => c,
+                };
+
+                for x in 0..scan.samples {
+                    let mut sum = 0.0;
+                    for &y in &rows {
+                        for px in x * span..(x + 1) * span {
+                            let pixel = image.get_pixel(px as u32, y as u32);
+                            let ycrcb = rgb_to_ycrcb(pixel[0], pixel[1], pixel[2]);
+                            sum += match channel {
+                                layout::Channel::Red => pixel[0] as f32 / 255.0,
+                                layout::Channel::Green => pixel[1] as f32 / 255.0,
+                                layout::Channel::Blue => pixel[2] as f32 / 255.0,
+                                layout::Channel::Luma => ycrcb.0,
+                                layout::Channel::RedDiff => ycrcb.1,
+                                _ => ycrcb.2,
+                            };
+                        }
+                    }
+                    let value = sum / (rows.len() * span) as f32;
+                    let freq = match channel {
+                        layout::Channel::RedDiff | layout::Channel::BlueDiff => self.chrominance_freq(value),
+                        _ => self.luminance_freq(value),
+                    };
+                    emit_tone(sink, osc, freq, pixel_ms as f32)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+impl FromStr for SSTVMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "R12" | "Robot12" => Ok(SSTVMode::R12),
+            "R24" | "Robot24" => Ok(SSTVMode::R24),
+            "R36" | "Robot36" => Ok(SSTVMode::R36),
+            "R72" | "Robot72" => Ok(SSTVMode::R72),
+            "M1" | "Martin1" => Ok(SSTVMode::M1),
+            "M2" | "Martin2" => Ok(SSTVMode::M2),
+            "M3" | "Martin3" => Ok(SSTVMode::M3),
+            "M4" | "Martin4" => Ok(SSTVMode::M4),
+            "S1" | "Scottie1" => Ok(SSTVMode::S1),
+            "S2" | "Scottie2" => Ok(SSTVMode::S2),
+            "S3" | "Scottie3" => Ok(SSTVMode::S3),
+            "S4" | "Scottie4" => Ok(SSTVMode::S4),
+            "SDX" | "ScottieDX" => Ok(SSTVMode::SDX),
+            "PD50" | "PD-50" => Ok(SSTVMode::PD50),
+            "PD90" | "PD-90" => Ok(SSTVMode::PD90),
+            "PD120" | "PD-120" => Ok(SSTVMode::PD120),
+            "PD160" | "PD-160" => Ok(SSTVMode::PD160),
+            "PD180" | "PD-180" => Ok(SSTVMode::PD180),
+            "PD240" | "PD-240" => Ok(SSTVMode::PD240),
+            "PD290" | "PD-290" => Ok(SSTVMode::PD290),
+            "SC2-30" | "Wraase30" => Ok(SSTVMode::SC2_30),
+            "SC2-60" | "Wraase60" => Ok(SSTVMode::SC2_60),
+            "SC2-120" | "Wraase120" => Ok(SSTVMode::SC2_120),
+            "SC2-180" | "Wraase180" => Ok(SSTVMode::SC2_180),
+            "P3" | "Pasokon3" => Ok(SSTVMode::P3),
+            "P5" | "Pasokon5" => Ok(SSTVMode::P5),
+            "P7" | "Pasokon7" => Ok(SSTVMode::P7),
+            "MP73" | "MP-73" => Ok(SSTVMode::MP73),
+            "MP115" | "MP-115" => Ok(SSTVMode::MP115),
+            "MP140" | "MP-140" => Ok(SSTVMode::MP140),
+            "MP175" | "MP-175" => Ok(SSTVMode::MP175),
+            "MR73" | "MR-73" => Ok(SSTVMode::MR73),
+            "MR90" | "MR-90" => Ok(SSTVMode::MR90),
+            "MR115" | "MR-115" => Ok(SSTVMode::MR115),
+            "MR140" | "MR-140" => Ok(SSTVMode::MR140),
+            "MR175" | "MR-175" => Ok(SSTVMode::MR175),
+            "ML180" | "ML-180" => Ok(SSTVMode::ML180),
+            "ML240" | "ML-240" => Ok(SSTVMode::ML240),
+            "ML280" | "ML-280" => Ok(SSTVMode::ML280),
+            "ML320" | "ML-320" => Ok(SSTVMode::ML320),
+            "MN73" | "MN-73" => Ok(SSTVMode::MN73),
+            "MN110" | "MN-110" => Ok(SSTVMode::MN110),
+            "MN140" | "MN-140" => Ok(SSTVMode::MN140),
+            "MC110" | "MC-110" => Ok(SSTVMode::MC110),
+            "MC140" | "MC-140" => Ok(SSTVMode::MC140),
+            "MC180" | "MC-180" => Ok(SSTVMode::MC180),
+            "BW8" | "RobotBW8" => Ok(SSTVMode::BW8),
+            "BW12" | "RobotBW12" => Ok(SSTVMode::BW12),
+            "BW24" | "RobotBW24" => Ok(SSTVMode::BW24),
+            "BW36" | "RobotBW36" => Ok(SSTVMode::BW36),
+            "AVT24" | "AVT-24" => Ok(SSTVMode::AVT24),
+            "AVT90" | "AVT-90" => Ok(SSTVMode::AVT90),
+            "AVT94" | "AVT-94" => Ok(SSTVMode::AVT94),
+            "AVT125" | "AVT-125" => Ok(SSTVMode::AVT125),
+            "FAX480" => Ok(SSTVMode::FAX480),
+            _ => Err(Error::UnknownMode(s.to_string())),
+        }
+    }
+}
+
+
+struct Oscillator {
+    pub sample_rate: u32,
+    phase: f32,
+    frac_samples: f32,
+    pub amplitude: f32,
+}
+impl Oscillator {
+    pub fn new(sample_rate: u32, amplitude: f32) -> Self {
+        Self {
+            sample_rate,
+            phase: 0.0,
+            frac_samples: 0.0,
+            amplitude: amplitude.clamp(0.0, 1.0),
+        }
+    }
+}
+
+
+//lowest sample rate that still carries the 2300 Hz white tone
+pub const MIN_SAMPLE_RATE: u32 = 8000;
+
+pub struct EncoderOptions {
+    //at least MIN_SAMPLE_RATE
+    pub sample_rate: u32,
+    //fraction of full scale, 0.0-1.0
+    pub amplitude: f32,
+    //calibration tones before the VIS header
+    pub calibration: bool,
+    //applied when the image isn't already at the mode's resolution
+    pub fit: Fit,
+    pub filter: image::imageops::FilterType,
+    //Gaussian sigma in pixels of the unsharp mask applied to every picture once it is at the mode's resolution, 0.0 turns it off
+    //offsets the soft horizontal response of the FM channel
+    pub sharpen: f32,
+    //drawn in order once the image is at the mode's resolution, e.g. a callsign banner
+    pub overlays: Vec<overlay::TextOverlay>,
+}
+
+impl Default for EncoderOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44100,
+            amplitude: 0.5,
+            calibration: true,
+            fit: Fit::Stretch,
+            filter: image::imageops::FilterType::Lanczos3,
+            sharpen: 0.0,
+            overlays: Vec::new(),
+        }
+    }
+}
+
+//turns images into 16-bit mono SSTV audio for one mode
+pub struct Encoder {
+    mode: SSTVMode,
+    options: EncoderOptions,
+}
+
+impl Encoder {
+    pub fn new(mode: SSTVMode, options: EncoderOptions) -> Result<Self, Error> {
+        if options.sample_rate < MIN_SAMPLE_RATE {
+            return Err(Error::InvalidArgument(format!("Sample rate must be at least {} Hz: {}", MIN_SAMPLE_RATE, options.sample_rate)));
+        }
+        Ok(Self {mode, options})
+    }
+    pub fn mode(&self) -> SSTVMode {
+        self.mode
+    }
+    pub fn options(&self) -> &EncoderOptions {
+        &self.options
+    }
+    //picture as it will be sent: fitted to the mode's resolution when it doesn't match, sharpened, then the overlays
+    pub fn prepare(&self, image: &image::RgbImage) -> image::RgbImage {
+        let (width, height) = self.mode.resolution();
+        let mut image = if image.dimensions() == (width, height) {
+            image.clone()
+        }
+        else {
+            self.options.fit.apply(image, width, height, self.options.filter)
+        };
+        if self.options.sharpen > 0.0 {
+            image = image::imageops::unsharpen(&image, self.options.sharpen, 0);
+        }
+        for overlay in &self.options.overlays {
+            overlay.draw(&mut image);
+        }
+        image
+    }
+    //lazily generated audio for the image, one line at a time
+    //the image is prepared first, see prepare()
+    pub fn samples(&self, image: &image::RgbImage) -> Samples {
+        let height = self.mode.resolution().1;
+        let image = self.prepare(image);
+        let layout = layout::layout(&self.mode);
+        Samples {
+            mode: self.mode,
+            calibration: self.options.calibration,
+            osc: Oscillator::new(self.options.sample_rate, self.options.amplitude),
+            lines: height as usize / layout.rows,
+            image,
+            layout,
+            next_line: None,
+            buffer: Vec::new(),
+            pos: 0,
+            done: false,
+            error: None,
+        }
+    }
+    //samples are generated at options.sample_rate, the sink is expected to run at the same rate
+    pub fn encode<S: SampleSink + ?Sized>(&self, image: &image::RgbImage, sink: &mut S) -> Result<(), Error> {
+        let mut samples = self.samples(image);
+        for sample in samples.by_ref() {
+            sink.write_sample(sample)?;
+        }
+        if let Some(e) = samples.error.take() {
+            return Err(e);
+        }
+        sink.flush()?;
+        Ok(())
+    }
+    //16-bit mono WAV at options.sample_rate
+    pub fn encode_wav<W: std::io::Write + std::io::Seek>(&self, image: &image::RgbImage, sink: W) -> Result<(), Error> {
+        let spec = hound::WavSpec{
+            channels: 1,
+            sample_rate: self.options.sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int
+        };
+        let mut writer = hound::WavWriter::new(sink, spec)?;
+        self.encode(image, &mut writer)?;
+        writer.finalize()?;
+        Ok(())
+    }
+}
+
+
+//encoder output as an iterator, each line is rendered only once playback reaches it
+pub struct Samples {
+    mode: SSTVMode,
+    calibration: bool,
+    osc: Oscillator,
+    image: image::RgbImage,
+    layout: layout::Layout,
+    lines: usize,
+    //None until the VIS header and lead are generated
+    next_line: Option<usize>,
+    buffer: Vec<f32>,
+    pos: usize,
+    done: bool,
+    //why generation stopped early, the iterator itself can only end
+    error: Option<Error>,
+}
+
+impl Samples {
+    //refills the buffer with the next part of the transmission, false once everything is sent
+    fn generate(&mut self) -> Result<bool, Error> {
+        self.buffer.clear();
+        self.pos = 0;
+        match self.next_line {
+            None => {
+                write_vis(&mut self.buffer, &mut self.osc, self.mode.vis_code(), self.mode.is_narrow(), self.calibration)?;
+                for segment in &self.layout.lead {
+                    self.mode.write_segment(&mut self.buffer, &mut self.osc, &self.image, &self.layout, segment, 0)?;
+                }
+                self.next_line = Some(0);
+            }
+            Some(line) if line < self.lines => {
+                for segment in &self.layout.segments {
+                    self.mode.write_segment(&mut self.buffer, &mut self.osc, &self.image, &self.layout, segment, line)?;
+                }
+                self.next_line = Some(line + 1);
+            }
+            Some(_) => return Ok(false),
+        }
+        Ok(true)
+    }
+    //the error that cut the transmission short, if any
+    pub fn error(&self) -> Option<&Error> {
+        self.error.as_ref()
+    }
+    //whole transmission, calibration and VIS header included
+    fn duration_ms(&self) -> f64 {
+        let calibration_ms = if self.calibration {800.0} else {0.0};
+        //leader, break, leader, start bit, 7 data bits and parity or the extended marker and mode bytes, stop bit
+        let vis_bits = if self.mode.vis_code() > 0x7F {2 + 16} else {2 + 8};
+        let vis_ms = 300.0 + 10.0 + 300.0 + vis_bits as f64 * 30.0;
+        calibration_ms + vis_ms + self.layout.lead_ms + self.lines as f64 * self.layout.period_ms
+    }
+}
+
+impl Iterator for Samples {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        while self.pos >= self.buffer.len() {
+            if self.done {
+                return None;
+            }
+            match self.generate() {
+                Ok(true) => {}
+                Ok(false) => self.done = true,
+                Err(e) => {
+                    self.error = Some(e);
+                    self.done = true;
+                }
+            }
+        }
+        self.pos += 1;
+        Some(self.buffer[self.pos - 1])
+    }
+}
+
+impl rodio::Source for Samples {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+    fn channels(&self) -> rodio::ChannelCount {
+        1
+    }
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.osc.sample_rate
+    }
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs_f64(self.duration_ms() / 1000.0))
+    }
+}
+
+fn rgb_to_ycrcb(red: u8, green: u8, blue: u8) -> (f32, f32, f32) {
+    let r: f32 = (red as f32)/255.0;
+    let g: f32 = (green as f32)/255.0;
+    let b: f32 = (blue as f32)/255.0;
+
+    let y = 0.299*r + 0.587*g + 0.114*b;
+    let cr = r - y;
+    let cb = b - y;
+
+    (y, cr, cb)
+}
+
+fn ycrcb_to_rgb(y: f32, cr: f32, cb: f32) -> (u8, u8, u8) {
+    let r = y + cr;
+    let b = y + cb;
+    let g = (y - 0.299*r - 0.114*b) / 0.587;
+
+    let to_u8 = |v: f32| (v * 255.0).round().clamp(0.0, 255.0) as u8;
+    (to_u8(r), to_u8(g), to_u8(b))
+}
+
+fn write_vis<S: SampleSink + ?Sized>(
+    sink: &mut S,
+    osc: &mut Oscillator,
+    vis_code: u16,
+    narrow: bool,
+    incl_calibration: bool
+) -> Result<(), Error> {
+
+    if incl_calibration {
+        emit_tone(sink, osc, 1900.0, 100.0)?;
+        emit_tone(sink, osc, 1500.0, 100.0)?;
+        emit_tone(sink, osc, 1900.0, 100.0)?;
+        emit_tone(sink, osc, 1500.0, 100.0)?;
+
+        emit_tone(sink, osc, 2300.0, 100.0)?;
+        emit_tone(sink, osc, 1500.0, 100.0)?;
+        emit_tone(sink, osc, 2300.0, 100.0)?;
+        emit_tone(sink, osc, 1500.0, 100.0)?;
+    }
+
+
+
+    const VIS_LEADER_MS: f32 = 300.0;
+    const VIS_BREAK_MS: f32 = 10.0;
+    const VIS_BIT_MS: f32 = 30.0;
+
+    let (vis_leader_hz, vis_bit_1_hz, vis_bit_0_hz, vis_bit_n_hz) = vis_tones(narrow);
+    //write VIS
+    emit_tone(sink, osc, vis_leader_hz, VIS_LEADER_MS)?;
+    emit_tone(sink, osc, vis_bit_n_hz, VIS_BREAK_MS)?;
+    emit_tone(sink, osc, vis_leader_hz, VIS_LEADER_MS)?;
+
+    //start bit
+    emit_tone(sink, osc, vis_bit_n_hz, VIS_BIT_MS)?;
+
+    //extended VIS sends the 0x23 marker as a whole byte, its clear top bit fails the parity check on purpose,
+    //then the mode byte, neither followed by a parity bit
+    let (groups, with_parity): (&[(u16, usize)], bool) = if vis_code > 0x7F {
+        (&[(vis_code & 0xFF, 8), (vis_code >> 8, 8)], false)
+    }
+    else {
+        (&[(vis_code, 7)], true)
+    };
+
+    for &(mut bits, count) in groups {
+        let mut parity = false;
+        for _ in 0..count {
+            let bit = bits & 1;
+            if bit == 1{
+                emit_tone(sink, osc, vis_bit_1_hz, VIS_BIT_MS)?;
+                parity = !parity;
+            }
+            else {
+                emit_tone(sink, osc, vis_bit_0_hz, VIS_BIT_MS)?;
+            }
+            bits >>= 1;
+        }
+        //parity bit
+        if with_parity {
+            emit_tone(sink, osc, if parity {vis_bit_1_hz} else {vis_bit_0_hz}, VIS_BIT_MS)?;
+        }
+    }
+    //stop bit
+    emit_tone(sink, osc, vis_bit_n_hz, VIS_BIT_MS)?;
+    Ok(())
+}
+
+//leader, bit 1, bit 0 and break/start/stop frequencies of the VIS header
+//narrow VIS keeps the header inside the 1900-2300 Hz narrowband channel
+fn vis_tones(narrow: bool) -> (f32, f32, f32, f32) {
+    if narrow {
+        (2300.0, 2100.0, 2200.0, 1900.0)
+    }
+    else {
+        (1900.0, 1100.0, 1300.0, 1200.0)
+    }
+}
+
+fn emit_tone<S: SampleSink + ?Sized>(
+    sink: &mut S,
+    osc: &mut Oscillator,
+    freq_hz: f32,
+    duration_ms: f32,
+) -> Result<(), Error> {
+    if duration_ms.is_nan() || duration_ms < 0.0 {
+        return Err(Error::InvalidDuration(duration_ms));
+    }
+    _emit_tone(sink, osc, freq_hz, duration_ms).map_err(Error::Output)
+}
+
+fn _emit_tone<S: SampleSink + ?Sized>(
+    sink: &mut S,
+    osc: &mut Oscillator,
+    freq_hz: f32,
+    duration_ms: f32,
+) -> std::io::Result<()> {
+    let sr = osc.sample_rate as f32;
+    let exact_samples = duration_ms * sr / 1000.0;
+    let total_samples = exact_samples + osc.frac_samples;
+    let samples_to_write = total_samples.floor() as usize;
+    osc.frac_samples = total_samples - (samples_to_write as f32);
+
+    let is_silence = freq_hz <= 0.0;
+    let phase_inc = if is_silence { 0.0 } else { 2.0 * PI * freq_hz / sr };
+
+    for _ in 0..samples_to_write {
+        let sample = if is_silence { 0.0 } else { osc.phase.sin() * osc.amplitude };
+
+        sink.write_sample(sample)?;
+
+        if !is_silence {
+            osc.phase += phase_inc;
+            if osc.phase >= 2.0 * PI {
+                osc.phase -= 2.0 * PI;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::Source;
+
+    #[test]
+    fn samples_duration_matches_length() {
+        for mode in [SSTVMode::S1, SSTVMode::MP73] {
+            let encoder = Encoder::new(mode, EncoderOptions::default()).unwrap();
+            let samples = encoder.samples(&image::RgbImage::new(1, 1));
+            let expected = samples.total_duration().unwrap().as_secs_f64() * encoder.options.sample_rate as f64;
+            let len = samples.count() as f64;
+            assert!((len - expected).abs() < 2.0, "{:?}: {} samples, {} expected", mode, len, expected);
+        }
+    }
+
+    #[test]
+    fn sample_rate_too_low_is_rejected() {
+        for (sample_rate, ok) in [(0, false), (7999, false), (8000, true)] {
+            let options = EncoderOptions {
+                sample_rate,
+                ..EncoderOptions::default()
+            };
+            assert_eq!(Encoder::new(SSTVMode::S1, options).is_ok(), ok, "{} Hz", sample_rate);
+        }
+    }
+
+    //dark left half, light right half
+    fn edge(width: u32, height: u32) -> image::RgbImage {
+        image::RgbImage::from_fn(width, height, |x, _| if x < width / 2 {image::Rgb([64, 64, 64])} else {image::Rgb([192, 192, 192])})
+    }
+
+    fn prepare(sharpen: f32, image: &image::RgbImage) -> image::RgbImage {
+        Encoder::new(SSTVMode::S1, EncoderOptions {
+            filter: image::imageops::FilterType::Nearest,
+            sharpen,
+            ..EncoderOptions::default()
+        })
+        .unwrap()
+        .prepare(image)
+    }
+
+    #[test]
+    fn sharpen_applies_at_any_input_size() {
+        //already at 320x256, and resized up to it
+        for source in [edge(320, 256), edge(160, 128)] {
+            let plain = prepare(0.0, &source);
+            assert_eq!(plain.get_pixel(159, 100)[0], 64);
+            assert_eq!(plain.get_pixel(160, 100)[0], 192);
+
+            //the mask overshoots either side of the edge and leaves flat areas alone
+            let sharp = prepare(1.0, &source);
+            assert!(sharp.get_pixel(159, 100)[0] < 64);
+            assert!(sharp.get_pixel(160, 100)[0] > 192);
+            assert_eq!(sharp.get_pixel(20, 100)[0], 64);
+        }
+    }
+}
@@ -1,546 +1,599 @@
+use std::collections::HashMap;
 use std::env;
-use std::str::FromStr;
-use std::f32::consts::PI;
 
 use image::GenericImageView;
-use rodio;
-
-
-#[derive(PartialEq, Eq, Debug)]
-enum SSTVMode {
-    R12,
-    R24,
-    R36,
-    R72,
-    M1,
-    M2,
-    M3,
-    M4,
-    S1,
-    S2,
-    S3,
-    S4,
-    SDX,
+use image::imageops::FilterType;
+
+use sstv_converter::{Encoder, EncoderOptions, Error, Fit, MIN_SAMPLE_RATE, SSTVMode, channel, decode, overlay, pattern, sink, template};
+
+fn main(){
+    let argv: Vec<String> = env::args().collect();
+
+    let result = if argv.len() > 1 && argv[1] == "decode" {
+        decode_main(&argv)
+    }
+    else if argv.len() > 1 && argv[1] == "simulate" {
+        simulate_main(&argv)
+    }
+    else {
+        encode_main(argv)
+    };
+
+    //each kind of failure exits with its own code, see Error::exit_code
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
 }
 
-impl SSTVMode {
-    fn resolution(&self) -> (u32, u32) {
-        match self {
-            SSTVMode::R12 => (160, 120),
-            SSTVMode::R24 => (160, 120),
-            SSTVMode::R36 => (320, 240),
-            SSTVMode::R72 => (320, 240),
-            SSTVMode::M1 => (320, 256),
-            SSTVMode::M2 => (160, 256),
-            SSTVMode::M3 => (320, 128),
-            SSTVMode::M4 => (160, 128),
-            SSTVMode::S1 => (320, 256),
-            SSTVMode::S2 => (160, 256),
-            SSTVMode::S3 => (320, 128),
-            SSTVMode::S4 => (160, 128),
-            SSTVMode::SDX => (320, 256),
-        }
+fn encode_main(argv: Vec<String>) -> Result<(), Error> {
+
+    //break down argv
+    let mut sstv_mode: SSTVMode = SSTVMode::S1;
+
+    let mut volume: f32 = 50.0;
+
+    let mut sample_rate: u32 = 44100;
+
+    let mut infile_path: String = String::from("");
+    let mut outfile_path: String = String::from("out.wav");
+
+    let mut calibration: bool = true;
+    let mut playback: bool = false;
+    let mut fit: Fit = Fit::Stretch;
+    let mut filter = FilterType::Lanczos3;
+    let mut sharpen: f32 = 0.0;
+    let mut overlays: Vec<overlay::TextOverlay> = Vec::new();
+    let mut template_path: Option<String> = None;
+    //slot name and image path
+    let mut slot_paths: Vec<(String, String)> = Vec::new();
+    let mut values: HashMap<String, String> = HashMap::new();
+    let mut test_pattern: Option<pattern::Pattern> = None;
+
+    parse_args(&argv, &mut sstv_mode, &mut volume, &mut sample_rate, &mut infile_path, &mut outfile_path, &mut calibration, &mut playback, &mut fit, &mut filter, &mut sharpen, &mut overlays, &mut template_path, &mut slot_paths, &mut values, &mut test_pattern)?;
+
+    println!("Mode: {:?}", sstv_mode);
+    println!("Volume: {}%", volume*100.0);
+    println!("Sample rate: {} Hz", sample_rate);
+    println!("Calibration signal: {:?}", calibration);
+    println!("Infile: {}", infile_path);
+    println!("Outfile: {}", outfile_path);
+    println!("Playback: {}", playback);
+    match fit {
+        Fit::Stretch => println!("Fit: stretch"),
+        Fit::Letterbox {pad} => println!("Fit: letterbox, pad #{:02x}{:02x}{:02x}", pad[0], pad[1], pad[2]),
+        Fit::Crop {focus} => println!("Fit: crop at {},{}", focus.0, focus.1),
     }
-    fn vis_code(&self) -> u8 {
-        match self {
-            SSTVMode::R12 => 0b0000000,
-            SSTVMode::R24 => 0b0000100,
-            SSTVMode::R36 => 0b0001000,
-            SSTVMode::R72 => 0b0001100,
-            SSTVMode::M1 => 0b0101100,
-            SSTVMode::M2 => 0b0101000,
-            SSTVMode::M3 => 0b0100100,
-            SSTVMode::M4 => 0b0100000,
-            SSTVMode::S1 => 0b0111100,
-            SSTVMode::S2 => 0b0111000,
-            SSTVMode::S3 => 0b0110100,
-            SSTVMode::S4 => 0b0110000,
-            SSTVMode::SDX => 0b1001100,
-        }
+    println!("Resize filter: {:?}", filter);
+    println!("Sharpen: {}", sharpen);
+    for overlay in &overlays {
+        println!("Overlay: {:?} at {:?}", overlay.text, overlay.anchor);
     }
-    fn color_scanline_ms(&self) -> f32 {
-        match self {
-            SSTVMode::R12 | SSTVMode::R24 | SSTVMode::R36 | SSTVMode::R72 => 0.0,
-            SSTVMode::M1 => 146.432,
-            SSTVMode::M2 => 73.216,
-            SSTVMode::M3 => 146.432,
-            SSTVMode::M4 => 73.216,
-            SSTVMode::S1 => 138.240,
-            SSTVMode::S2 => 88.064,
-            SSTVMode::S3 => 138.240,
-            SSTVMode::S4 => 88.064,
-            SSTVMode::SDX => 345.600,
+
+    //a test pattern stands in for infile
+    let pattern_image = test_pattern.map(|pattern| {
+        println!("Test pattern: {:?}", pattern);
+        pattern.render(sstv_mode)
+    });
+
+    let image = match template_path {
+        Some(template_path) => {
+            println!("Template: {}", template_path);
+            let template = template::Template::load(&template_path)?;
+            //infile or the test pattern goes to the main slot unless it is given explicitly
+            if !infile_path.is_empty() && !slot_paths.iter().any(|(name, _)| name == "main") {
+                slot_paths.push((String::from("main"), infile_path.clone()));
+            }
+            let mut slots = HashMap::new();
+            if let Some(image) = pattern_image && !slot_paths.iter().any(|(name, _)| name == "main") {
+                slots.insert(String::from("main"), image);
+            }
+            for (name, path) in slot_paths {
+                println!("Slot {}: {}", name, path);
+                let image = image::open(&path).map_err(Error::InputImage)?;
+                slots.insert(name, image.to_rgb8());
+            }
+            let image = template.render(sstv_mode, &slots, &values, filter)?;
+            println!("Template rendered at {}x{}", image.width(), image.height());
+            image
         }
-    }
-    fn write_scanlines<W: std::io::Write + std::io::Seek>(
-        &self,
-        writer: &mut hound::WavWriter<W>,
-        osc: &mut Oscillator,
-        image: &image::ImageBuffer<image::Rgb<u8>, Vec<u8>>
-        ) {
-        match self {
-            SSTVMode::M1 | SSTVMode::M2 | SSTVMode::M3 | SSTVMode::M4 => {
-                let width = self.resolution().0 as usize;
-                let height = self.resolution().1 as usize;
-
-                const LINE_SYNC_HZ: f32 = 1200.0;
-                const SEP_HZ: f32 = 1500.0;
-
-                const LINE_SYNC_MS: f32 = 4.862;
-                let color_scan_ms = self.color_scanline_ms();
-                const SEP_MS: f32 = 0.572;
-
-                let pixel_ms = color_scan_ms / width as f32;
-
-                for y in 0..height {
-                    //line sync
-                    emit_tone(writer, osc, LINE_SYNC_HZ, LINE_SYNC_MS);
-                    //separator
-                    emit_tone(writer, osc, SEP_HZ, SEP_MS);
-                    //green
-                    for x in 0..width {
-                        let pixel = image.get_pixel(x as u32, y as u32);
-                        let g = pixel[1] as f32 / 255.0;
-                        let freq = 1500.0 + (2300.0 - 1500.0) * g;
-                        emit_tone(writer, osc, freq, pixel_ms);
-                    }
-                    //separator
-                    emit_tone(writer, osc, SEP_HZ, SEP_MS);
-
-                    //blue
-                    for x in 0..width {
-                        let pixel = image.get_pixel(x as u32, y as u32);
-                        let b = pixel[2] as f32 / 255.0;
-                        let freq = 1500.0 + (2300.0 - 1500.0) * b;
-                        emit_tone(writer, osc, freq, pixel_ms);
-                    }
-                    //separator
-                    emit_tone(writer, osc, SEP_HZ, SEP_MS);
-                    //red
-                    for x in 0..width {
-                        let pixel = image.get_pixel(x as u32, y as u32);
-                        let r = pixel[0] as f32 / 255.0;
-                        let freq = 1500.0 + (2300.0 - 1500.0) * r;
-                        emit_tone(writer, osc, freq, pixel_ms);
-                    }
-                    //separator
-                    emit_tone(writer, osc, SEP_HZ, SEP_MS);
+        None => match pattern_image {
+            Some(image) => image,
+            None => {
+                //load image
+                let image = image::open(infile_path)
+                    .map_err(Error::InputImage)?;
+                let image_resolution = image.dimensions();
+                //the encoder fits the image to the target resolution
+                let target_resolution = sstv_mode.resolution();
+                if image_resolution != target_resolution {
+                    println!("Image resized from {}x{} to {}x{}", image_resolution.0, image_resolution.1, target_resolution.0, target_resolution.1);
                 }
+                //to rgb8
+                image.to_rgb8()
             }
-            SSTVMode::S1 | SSTVMode::S2 | SSTVMode::S3 | SSTVMode::S4 | SSTVMode::SDX => {
-                let width = self.resolution().0 as usize;
-                let height = self.resolution().1 as usize;
+        },
+    };
 
-                const LINE_SYNC_HZ: f32 = 1200.0;
-                const SEP_HZ: f32 = 1500.0;
+    let encoder = Encoder::new(sstv_mode, EncoderOptions {
+        sample_rate,
+        amplitude: volume,
+        calibration,
+        fit,
+        filter,
+        sharpen,
+        overlays,
+    })?;
+
+    //playback pulls samples as it goes, so it starts right away while the file is written
+    let playback = if playback {
+        println!("Playback: ");
+        let stream_handle = rodio::OutputStreamBuilder::open_default_stream()
+            .map_err(|e| Error::Playback(e.to_string()))?;
+        let sink = rodio::Sink::connect_new(stream_handle.mixer());
+        sink.append(encoder.samples(&image));
+        Some((stream_handle, sink))
+    }
+    else {
+        None
+    };
 
-                const LINE_SYNC_MS: f32 = 9.0;
-                let color_scan_ms = self.color_scanline_ms();
-                const SEP_MS: f32 = 1.5;
+    let file = std::fs::File::create(&outfile_path)?;
+    let file = std::io::BufWriter::new(file);
 
-                let pixel_ms = color_scan_ms / width as f32;
+    println!("Writing VIS header and image scanlines");
+    //.raw and .pcm get headerless samples, anything else a wav file
+    let raw = outfile_path.ends_with(".raw") || outfile_path.ends_with(".pcm");
+    if raw {
+        encoder.encode(&image, &mut sink::PcmWriter::new(file))?;
+    }
+    else {
+        encoder.encode_wav(&image, file)?;
+    }
 
-                for y in 0..height {
+    println!("Done");
 
-                    //separator
-                    emit_tone(writer, osc, SEP_HZ, SEP_MS);
 
-                    //green
-                    for x in 0..width {
-                        let pixel = image.get_pixel(x as u32, y as u32);
-                        let g = pixel[1] as f32 / 255.0;
-                        let freq = 1500.0 + (2300.0 - 1500.0) * g;
-                        emit_tone(writer, osc, freq, pixel_ms);
-                    }
+    if let Some((_stream_handle, sink)) = playback {
+        sink.sleep_until_end();
+    }
 
-                    //separator
-                    emit_tone(writer, osc, SEP_HZ, SEP_MS);
+    Ok(())
+}
 
-                    //blue
-                    for x in 0..width {
-                        let pixel = image.get_pixel(x as u32, y as u32);
-                        let b = pixel[2] as f32 / 255.0;
-                        let freq = 1500.0 + (2300.0 - 1500.0) * b;
-                        emit_tone(writer, osc, freq, pixel_ms);
-                    }
+fn decode_main(argv: &[String]) -> Result<(), Error> {
+    //None reads the mode from the VIS header
+    let mut sstv_mode: Option<SSTVMode> = None;
+    let mut slant_correction: bool = true;
+    let mut free_running: bool = false;
+    let mut correlate: bool = true;
+    //only used for raw PCM on stdin
+    let mut sample_rate: u32 = 48000;
+    let mut update_rows: u32 = 16;
 
-                    //line sync
-                    emit_tone(writer, osc, LINE_SYNC_HZ, LINE_SYNC_MS);
+    let mut infile_path: String = String::from("");
+    let mut outfile_path: String = String::from("out.png");
 
-                    //separator
-                    emit_tone(writer, osc, SEP_HZ, SEP_MS);
+    parse_decode_args(argv, &mut sstv_mode, &mut infile_path, &mut outfile_path, &mut slant_correction, &mut free_running, &mut correlate, &mut sample_rate, &mut update_rows)?;
 
-                    //red
-                    for x in 0..width {
-                        let pixel = image.get_pixel(x as u32, y as u32);
-                        let r = pixel[0] as f32 / 255.0;
-                        let freq = 1500.0 + (2300.0 - 1500.0) * r;
-                        emit_tone(writer, osc, freq, pixel_ms);
-                    }
+    match sstv_mode {
+        Some(mode) => println!("Mode: {:?}", mode),
+        None => println!("Mode: from VIS"),
+    }
+    println!("Slant correction: {}", slant_correction);
+    println!("Free-running: {}", free_running);
+    if free_running {
+        println!("Line correlation: {}", correlate);
+    }
+    println!("Infile: {}", infile_path);
+    println!("Outfile: {}", outfile_path);
 
-                }
-            }
-            SSTVMode::R24 => {
-                let width = self.resolution().0 as usize;
-                let height = self.resolution().1 as usize;
-                
-                let half_chroma: bool = (*self == SSTVMode::R12) || (*self == SSTVMode::R36);
-
-                const LINE_SYNC_HZ: f32 = 1200.0;
-                const COLOR_SYNC1_HZ: f32 = 1500.0;
-                const COLOR_SYNC2_HZ: f32 = 2300.0;
-                const SEP_HZ: f32 = 1500.0;
-
-                //SKIBIDI
-                let line_sync_ms: f32 = match self {
-                    SSTVMode::R12 => {7.0},
-                    SSTVMode::R24 => {8.0},
-                    SSTVMode::R36 => {9.0},
-                    SSTVMode::R72 => {8.5},
-                    _ => {0.0}
-                };
-                let color_sync_ms: f32 = match self {
-                    SSTVMode::R12 => {3.0},
-                    SSTVMode::R24 => {4.5},
-                    SSTVMode::R36 => {4.5},
-                    SSTVMode::R72 => {4.75},
-                    _ => {0.0}
-                };
-                
-                let y_scan_ms: f32 = match self {
-                    SSTVMode::R12 => {60.0},
-                    SSTVMode::R24 => {91.0},
-                    SSTVMode::R36 => {88.0},
-                    SSTVMode::R72 => {138.0},
-                    _ => {0.0}
-                };
-                let color_scan_ms: f32 = match self {
-                    SSTVMode::R12 => {30.0},
-                    SSTVMode::R24 => {49.0},
-                    SSTVMode::R36 => {44.0},
-                    SSTVMode::R72 => {69.0},
-                    _ => {0.0}
-                };
-                const SEP_MS: f32 = 3.0;
-                const SEP_SHORT_MS: f32 = 1.5;
-
-                let y_pixel_ms = y_scan_ms / width as f32;
-                let color_pixel_ms = color_scan_ms / width as f32;
-
-                let mut no_vel = true;
-                for y in 0..height {
-                    let mut totalitarianism: f32 = 0.0;
-
-                    let oah = 1.0;
-                    //line sync
-                    emit_tone(writer, osc, LINE_SYNC_HZ, line_sync_ms+0.25+oah);
-                    totalitarianism += line_sync_ms+0.25+oah;
-                    //separator
-                    emit_tone(writer, osc, SEP_HZ, SEP_MS-0.5-oah);
-                    totalitarianism += SEP_MS-0.5-oah;
-                    //Luminance
-                    for x in 0..width {
-                        let pixel = image.get_pixel(x as u32, y as u32);
-                        let ycrcb = rgb_to_ycrcb(pixel[0], pixel[1], pixel[2]);
-                        let y = ycrcb.0;
-                        let freq: f32 = 1500.0 + (2300.0 - 1500.0) * y;
-                        emit_tone(writer, osc, freq, y_pixel_ms);
-                        totalitarianism += y_pixel_ms;
-                    }
-
-                    let inc_separator = -1.0;
-
-                    //chrominance sync
-                    let syn_adjust_1 = -1.25-(0.25/2.0);
-                    emit_tone(writer, osc, if half_chroma{if y%2==0{COLOR_SYNC1_HZ}else{COLOR_SYNC2_HZ}}else{COLOR_SYNC1_HZ}, color_sync_ms+syn_adjust_1+inc_separator-3.0);
-
-                    totalitarianism += color_sync_ms + syn_adjust_1 + inc_separator-3.0;
-
-                    //short separator
-                    //emit_tone(writer, osc, if half_chroma{SEP_HZ}else{1500.0}, SEP_SHORT_MS-1.0);
-                    
-                    //totalitarianism += SEP_SHORT_MS;
-                    
-                    //Chrominance
-                    for x in 0..width {
-                        let pixel = image.get_pixel(x as u32, y as u32);
-                        let ycrcb = rgb_to_ycrcb(pixel[0], pixel[1], pixel[2]);
-                        let chrominance = if half_chroma{if y%2==0{ycrcb.1}else{ycrcb.2}}else{ycrcb.1};
-                        let freq = 1900.0 + 400.0 * chrominance;
-                        emit_tone(writer, osc, freq, color_pixel_ms);
-                        totalitarianism += color_pixel_ms;
-                    }
-
-                    let syn_adjust_2 = -1.25-(0.25/2.0);
-                    if !half_chroma {
-                        //chrominance sync b
-                        emit_tone(writer, osc, COLOR_SYNC2_HZ, color_sync_ms+syn_adjust_2-inc_separator-3.0);
-                        totalitarianism += color_sync_ms + syn_adjust_2-inc_separator-3.0;
-                        //emit_tone(writer, osc, 1900.0, SEP_SHORT_MS-1.0);
-                        //totalitarianism += SEP_SHORT_MS;
-                        //Chrominance b
-                        for x in 0..width {
-                            let pixel = image.get_pixel(x as u32, y as u32);
-                            let ycrcb = rgb_to_ycrcb(pixel[0], pixel[1], pixel[2]);
-                            let chrominance = ycrcb.2;
-                            let freq = 1900.0 + 400.0 * chrominance;
-                            emit_tone(writer, osc, freq, color_pixel_ms);
-                            totalitarianism += color_pixel_ms;
-                        }
-                    }
-                    
-                    if no_vel {
-                        no_vel = false;
-                        println!("MS: {}", totalitarianism);
-                    }
-                }
-            }
-            
-            SSTVMode::R12 | SSTVMode::R24 | SSTVMode::R36 | SSTVMode::R72 => {
-                let width = self.resolution().0 as usize;
-                let height = self.resolution().1 as usize;
-                
-                let half_chroma: bool = (*self == SSTVMode::R12) || (*self == SSTVMode::R36);
-
-                const LINE_SYNC_HZ: f32 = 1200.0;
-                const COLOR_SYNC1_HZ: f32 = 1500.0;
-                const COLOR_SYNC2_HZ: f32 = 2300.0;
-                const SEP_HZ: f32 = 1500.0;
-
-                let line_sync_ms: f32 = match self {
-                    SSTVMode::R12 => {7.0},
-                    SSTVMode::R24 => {9.0},
-                    SSTVMode::R36 => {9.0},
-                    SSTVMode::R72 => {8.5},
-                    _ => {0.0}
-                };
-                let color_sync_ms: f32 = match self {
-                    SSTVMode::R12 => {3.0},
-                    SSTVMode::R24 => {4.5},
-                    SSTVMode::R36 => {4.5},
-                    SSTVMode::R72 => {4.75},
-                    _ => {0.0}
-                };
-
-                let y_scan_ms: f32 = match self {
-                    SSTVMode::R12 => {60.0},
-                    SSTVMode::R24 => {91.0},
-                    SSTVMode::R36 => {88.0},
-                    SSTVMode::R72 => {138.0},
-                    _ => {0.0}
-                };
-                let color_scan_ms: f32 = match self {
-                    SSTVMode::R12 => {30.0},
-                    SSTVMode::R24 => {45.0},
-                    SSTVMode::R36 => {44.0},
-                    SSTVMode::R72 => {69.0},
-                    _ => {0.0}
-                };
-                const SEP_MS: f32 = 3.0;
-                const SEP_SHORT_MS: f32 = 1.5;
-
-                let y_pixel_ms = y_scan_ms / width as f32;
-                let color_pixel_ms = color_scan_ms / width as f32;
-
-                let mut no_vel = true;
-                for y in 0..height {
-                    let mut totalitarianism: f32 = 0.0;
-                    //line sync
-                    emit_tone(writer, osc, LINE_SYNC_HZ, line_sync_ms);
-                    totalitarianism += line_sync_ms;
-                    //separator
-                    emit_tone(writer, osc, SEP_HZ, SEP_MS);
-                    totalitarianism += SEP_MS;
-                    //Luminance
-                    for x in 0..width {
-                        let pixel = image.get_pixel(x as u32, y as u32);
-                        let ycrcb = rgb_to_ycrcb(pixel[0], pixel[1], pixel[2]);
-                        let y = ycrcb.0;
-                        let freq: f32 = 1500.0 + (2300.0 - 1500.0) * y;
-                        emit_tone(writer, osc, freq, y_pixel_ms);
-                        totalitarianism += y_pixel_ms;
-                    }
-
-                    //chrominance sync
-                    emit_tone(writer, osc, if half_chroma{if y%2==0{COLOR_SYNC1_HZ}else{COLOR_SYNC2_HZ}}else{COLOR_SYNC1_HZ}, color_sync_ms);
-
-                    totalitarianism += color_sync_ms;
-
-                    //short separator
-                    emit_tone(writer, osc, if half_chroma{SEP_HZ}else{1900.0}, SEP_SHORT_MS);
-                    
-                    totalitarianism += SEP_SHORT_MS;
-
-                    //Chrominance
-                    for x in 0..width {
-                        let pixel = image.get_pixel(x as u32, y as u32);
-                        let ycrcb = rgb_to_ycrcb(pixel[0], pixel[1], pixel[2]);
-                        let chrominance = if half_chroma{if y%2==0{ycrcb.1}else{ycrcb.2}}else{ycrcb.1};
-                        let freq = 1900.0 + 400.0 * chrominance;
-                        emit_tone(writer, osc, freq, color_pixel_ms);
-                        totalitarianism += color_pixel_ms;
-                    }
-
-
-                    if !half_chroma {
-                        //chrominance sync b
-                        emit_tone(writer, osc, COLOR_SYNC2_HZ, color_sync_ms);
-                        totalitarianism += color_sync_ms;
-                        emit_tone(writer, osc, 1900.0, SEP_SHORT_MS);
-                        totalitarianism += SEP_SHORT_MS;
-                        //Chrominance b
-                        for x in 0..width {
-                            let pixel = image.get_pixel(x as u32, y as u32);
-                            let ycrcb = rgb_to_ycrcb(pixel[0], pixel[1], pixel[2]);
-                            let chrominance = ycrcb.2;
-                            let freq = 1900.0 + 400.0 * chrominance;
-                            emit_tone(writer, osc, freq, color_pixel_ms);
-                            totalitarianism += color_pixel_ms;
-                        }
-                    }
-                    
-                    if no_vel {
-                        no_vel = false;
-                        println!("MS: {}", totalitarianism);
-                    }
-                }
-            }
+    let options = decode::DecodeOptions {
+        mode: sstv_mode,
+        slant_correction,
+        free_running,
+        correlate,
+    };
 
-        }
+    if infile_path == "-" {
+        println!("Sample rate: {} Hz", sample_rate);
+        println!("Update every {} lines", update_rows);
+        return stream_main(options, sample_rate, update_rows, &outfile_path);
     }
-}
-impl FromStr for SSTVMode {
-    type Err = String;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "R12" | "Robot12" => Ok(SSTVMode::R12),
-            "R24" | "Robot24" => Ok(SSTVMode::R24),
-            "R36" | "Robot36" => Ok(SSTVMode::R36),
-            "R72" | "Robot72" => Ok(SSTVMode::R72),
-            "M1" | "Martin1" => Ok(SSTVMode::M1),
-            "M2" | "Martin2" => Ok(SSTVMode::M2),
-            "M3" | "Martin3" => Ok(SSTVMode::M3),
-            "M4" | "Martin4" => Ok(SSTVMode::M4),
-            "S1" | "Scottie1" => Ok(SSTVMode::S1),
-            "S2" | "Scottie2" => Ok(SSTVMode::S2),
-            "S3" | "Scottie3" => Ok(SSTVMode::S3),
-            "S4" | "Scottie4" => Ok(SSTVMode::S4),
-            "SDX" | "ScottieDX" => Ok(SSTVMode::SDX),
-            _ => Err(format!("Unknown SSTV mode: {}", s)),
-        }
-    }
-}
 
+    let (samples, sample_rate) = decode::read_wav(&infile_path)?;
+    println!("Sample rate: {} Hz", sample_rate);
 
-struct Oscillator {
-    pub sample_rate: u32,
-    phase: f32,
-    frac_samples: f32,
-    pub amplitude: f32,
-}
-impl Oscillator {
-    pub fn new(sample_rate: u32, amplitude: f32) -> Self {
-        Self {
-            sample_rate,
-            phase: 0.0,
-            frac_samples: 0.0,
-            amplitude: amplitude.clamp(0.0, 1.0),
-        }
+    println!("Decoding image scanlines");
+    let decoded = decode::decode(&samples, sample_rate, &options)?;
+    println!("Decoded mode: {:?}", decoded.mode);
+    if let Some(ppm) = decoded.clock_error_ppm {
+        println!("Clock error: {:+.1} ppm (true sample rate {:.1} Hz)", ppm, sample_rate as f64 * (1.0 + ppm / 1e6));
     }
+
+    decoded.image.save(&outfile_path)
+        .map_err(Error::OutputImage)?;
+
+    println!("Done");
+    Ok(())
 }
 
-fn main(){
-    let mut argv: Vec<String> = env::args().collect();
+//decodes raw 16-bit little endian mono PCM from stdin until it ends
+//the picture being received is written to outfile every update_rows rows, finished pictures to numbered files next to it
+fn stream_main(options: decode::DecodeOptions, sample_rate: u32, update_rows: u32, outfile_path: &str) -> Result<(), Error> {
+    use std::io::Read;
+
+    let path = std::path::Path::new(outfile_path);
+    let stem = path.with_extension("");
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("png");
+    let mut pictures = 0;
+    let mut save_picture = |decoded: decode::Decoded| -> Result<(), Error> {
+        pictures += 1;
+        let picture_path = format!("{}-{:04}.{}", stem.display(), pictures, extension);
+        decoded.image.save(&picture_path)
+            .map_err(Error::OutputImage)?;
+        println!("Saved {:?} picture to {}", decoded.mode, picture_path);
+        Ok(())
+    };
 
-    //break down argv
-    let mut sstv_mode: SSTVMode = SSTVMode::S1;
+    let mut decoder = decode::StreamDecoder::new(sample_rate, options, update_rows);
+    let mut stdin = std::io::stdin().lock();
+    //a quarter second of audio per read
+    let mut bytes = vec![0u8; (sample_rate as usize / 4) * 2];
+    let mut pending = 0;
+    println!("Listening on stdin");
+    loop {
+        let read = match stdin.read(&mut bytes[pending..]) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Error::InputAudio(hound::Error::IoError(e))),
+        };
+        let filled = pending + read;
+        //an odd byte waits for the rest of its sample
+        let whole = filled - filled % 2;
+        let samples: Vec<f32> = bytes[..whole]
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+            .collect();
+        bytes.copy_within(whole..filled, 0);
+        pending = filled - whole;
+
+        for event in decoder.push(&samples) {
+            match event {
+                decode::StreamEvent::Started(mode) => println!("Receiving {:?}", mode),
+                decode::StreamEvent::Partial(decoded, rows) => {
+                    println!("Received {}/{} lines", rows, decoded.mode.resolution().1);
+                    decoded.image.save(outfile_path)
+                        .map_err(Error::OutputImage)?;
+                }
+                decode::StreamEvent::Finished(decoded) => {
+                    decoded.image.save(outfile_path)
+                        .map_err(Error::OutputImage)?;
+                    save_picture(decoded)?;
+                }
+            }
+        }
+    }
 
-    let mut volume: f32 = 50.0;
+    if let Some(decoded) = decoder.finish() {
+        println!("Stream ended during the picture");
+        save_picture(decoded)?;
+    }
+    println!("Done");
+    Ok(())
+}
 
-    let mut sample_rate: u32 = 44100;
+fn simulate_main(argv: &[String]) -> Result<(), Error> {
+    let mut options = channel::ChannelOptions {
+        snr_db: None,
+        offset_hz: 0.0,
+        fading: None,
+        skew_ppm: 0.0,
+        seed: 1,
+    };
 
     let mut infile_path: String = String::from("");
-    let mut outfile_path: String = String::from("out.wav");
+    let mut outfile_path: String = String::from("sim.wav");
 
-    let mut calibration: bool = true;
-    let mut playback: bool = false;
-
-    parse_args(&mut argv, &mut sstv_mode, &mut volume, &mut sample_rate, &mut infile_path, &mut outfile_path, &mut calibration, &mut playback);
+    parse_simulate_args(argv, &mut options, &mut infile_path, &mut outfile_path)?;
 
-    println!("Mode: {:?}", sstv_mode);
-    println!("Volume: {}%", volume*100.0);
-    println!("Sample rate: {} Hz", sample_rate);
-    println!("Calibration signal: {:?}", calibration);
+    match options.snr_db {
+        Some(snr) => println!("SNR: {} dB in 3 kHz", snr),
+        None => println!("SNR: no noise"),
+    }
+    println!("Tuning offset: {} Hz", options.offset_hz);
+    match options.fading {
+        Some(fading) => println!("Fading: {} ms delay, {} Hz Doppler spread", fading.delay_ms, fading.spread_hz),
+        None => println!("Fading: none"),
+    }
+    println!("Clock skew: {} ppm", options.skew_ppm);
     println!("Infile: {}", infile_path);
     println!("Outfile: {}", outfile_path);
-    println!("Playback: {}", playback);
 
-    //load image
-    let image = image::open(infile_path)
-        .expect("Failed to open image");
-    let image_resolution = image.dimensions();
-    //to rgb8
-    let image = image.to_rgb8();
-    //resize to target resolution
-    let target_resolution = sstv_mode.resolution(); 
-    let image = image::imageops::resize(
-        &image,
-        target_resolution.0,
-        target_resolution.1,
-        image::imageops::FilterType::Nearest
-    );
-    println!("Image resized from {}x{} to {}x{}", image_resolution.0, image_resolution.1, target_resolution.0, target_resolution.1);
-
-    //make wav file
+    let (samples, sample_rate) = decode::read_wav(&infile_path)?;
+    println!("Sample rate: {} Hz", sample_rate);
+
+    println!("Simulating channel");
+    let samples = channel::simulate(&samples, sample_rate, &options);
+
     let spec = hound::WavSpec{
         channels: 1,
-        sample_rate: sample_rate,
+        sample_rate,
         bits_per_sample: 16,
         sample_format: hound::SampleFormat::Int
     };
+    let mut writer = hound::WavWriter::create(&outfile_path, spec)?;
+    for sample in samples {
+        let sample = (sample * i16::MAX as f32).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
 
-    let mut writer = hound::WavWriter::create(&outfile_path, spec)
-        .expect("Failed to create wav file");
+    println!("Done");
+    Ok(())
+}
 
-    let mut osc = Oscillator::new(sample_rate, volume);
+fn parse_simulate_args(args: &[String], options: &mut channel::ChannelOptions, infile_path: &mut String, outfile_path: &mut String) -> Result<(), Error> {
+    let helpmsg = format!(r#"Usage: {} simulate infile.wav [options]
+Options:
+  -h, --help                Display this text
+  -o <filename>             Specify output wav file name(default sim.wav)
+  -n, --snr <dB>            Specify signal to noise ratio in a 3 kHz bandwidth(default no noise)
+  -t, --offset <Hz>         Specify tuning offset(default 0)
+  -f, --fading <preset>     Specify Watterson fading: none, good, moderate, poor, flutter(default none)
+  -d, --delay <ms>          Specify fading path delay, enables fading(default 1 ms with -w)
+  -w, --spread <Hz>         Specify fading Doppler spread, enables fading(default 0.5 Hz with -d)
+  -k, --skew <ppm>          Specify receiver sample clock error(default 0)
+  -r, --seed <num>          Specify random seed for noise and fading(default 1)
+"#, args[0]);
 
-    write_vis(&mut writer, &mut osc, sstv_mode.vis_code(), calibration);
+    if args.len() < 3 { //at least 1 arg after simulate needed
+        print!("{}", helpmsg);
+        std::process::exit(0);
+    }
 
-    //sync and write image scanlines
-    println!("Writing image scanlines");
+    let mut flag_output = false;
+    let mut flag_snr = false;
+    let mut flag_offset = false;
+    let mut flag_fading = false;
+    let mut flag_delay = false;
+    let mut flag_spread = false;
+    let mut flag_skew = false;
+    let mut flag_seed = false;
+
+    //custom delay or spread starts from the moderate preset
+    let moderate = channel::Fading::preset("moderate").unwrap();
+
+    for arg in &args[2..] {
+        let arg: &str = arg;
+        if flag_output {
+            flag_output = false;
+            *outfile_path = arg.to_string();
+            continue;
+        }
+        if flag_snr {
+            flag_snr = false;
+            options.snr_db = Some(arg.parse().map_err(|_| invalid("Invalid SNR", arg))?);
+            continue;
+        }
+        if flag_offset {
+            flag_offset = false;
+            options.offset_hz = arg.parse().map_err(|_| invalid("Invalid tuning offset", arg))?;
+            continue;
+        }
+        if flag_fading {
+            flag_fading = false;
+            options.fading = if arg.eq_ignore_ascii_case("none") {
+                None
+            }
+            else {
+                Some(channel::Fading::preset(arg).ok_or_else(|| invalid("Invalid fading preset", arg))?)
+            };
+            continue;
+        }
+        if flag_delay {
+            flag_delay = false;
+            options.fading.get_or_insert(moderate).delay_ms = arg.parse().map_err(|_| invalid("Invalid fading delay", arg))?;
+            continue;
+        }
+        if flag_spread {
+            flag_spread = false;
+            options.fading.get_or_insert(moderate).spread_hz = arg.parse().map_err(|_| invalid("Invalid Doppler spread", arg))?;
+            continue;
+        }
+        if flag_skew {
+            flag_skew = false;
+            options.skew_ppm = arg.parse().map_err(|_| invalid("Invalid clock skew", arg))?;
+            continue;
+        }
+        if flag_seed {
+            flag_seed = false;
+            options.seed = arg.parse().map_err(|_| invalid("Invalid seed", arg))?;
+            continue;
+        }
 
-    sstv_mode.write_scanlines(&mut writer, &mut osc, &image);
+        match arg {
+            "-h" | "--help" => {
+                print!("{}", helpmsg);
+                std::process::exit(0);
+            }
+            "-o" => {
+                flag_output = true;
+            }
+            "-n" | "--snr" => {
+                flag_snr = true;
+            }
+            "-t" | "--offset" => {
+                flag_offset = true;
+            }
+            "-f" | "--fading" => {
+                flag_fading = true;
+            }
+            "-d" | "--delay" => {
+                flag_delay = true;
+            }
+            "-w" | "--spread" => {
+                flag_spread = true;
+            }
+            "-k" | "--skew" => {
+                flag_skew = true;
+            }
+            "-r" | "--seed" => {
+                flag_seed = true;
+            }
+            _ => {
+                *infile_path = arg.to_string();
+            }
+        }
+    }
+    Ok(())
+}
 
-    writer.finalize().unwrap();
+#[allow(clippy::too_many_arguments)]
+fn parse_decode_args(args: &[String], mode: &mut Option<SSTVMode>, infile_path: &mut String, outfile_path: &mut String, slant_correction: &mut bool, free_running: &mut bool, correlate: &mut bool, sample_rate: &mut u32, update_rows: &mut u32) -> Result<(), Error> {
+    let helpmsg = format!(r#"Usage: {0} decode infile [options]
+       {0} decode - [options]   (raw 16-bit little endian mono PCM from stdin, e.g. from rtl_fm)
+Options:
+  -h, --help                Display this text
+  -m, --mode <mode>         Specify SSTV mode of the recording(default detected from VIS)
+  -o <filename>             Specify output image file name(default out.png)
+  -l <bool>                 Specify slant and sample clock correction(default true)
+  -f <bool>                 Specify free-running decode that ignores line syncs, for noisy recordings(default false)
+  -x <bool>                 Specify free-running line period refinement by line cross-correlation(default true)
+
+Stdin options:
+  -s, --sample-rate <num>   Specify sample rate of the PCM input(default 48000)
+  -n <num>                  Specify lines between updates of the partial picture in outfile(default 16)
+                            finished pictures are saved as numbered files, e.g. out-0001.png
+"#, args[0]);
 
-    println!("Done");
+    if args.len() < 3 { //at least 1 arg after decode needed
+        print!("{}", helpmsg);
+        std::process::exit(0);
+    }
 
+    let mut flag_mode = false;
+    let mut flag_output = false;
+    let mut flag_slant = false;
+    let mut flag_free_running = false;
+    let mut flag_correlate = false;
+    let mut flag_sample_rate = false;
+    let mut flag_update = false;
 
-    if playback {
-        println!("Playback: ");
-        let stream_handle = rodio::OutputStreamBuilder::open_default_stream().expect("Can't open default audio stream");
-        let sink = rodio::Sink::connect_new(stream_handle.mixer());
+    for arg in &args[2..] {
+        let arg: &str = arg;
 
-        let file = std::fs::File::open(outfile_path).expect("Can't open wav file for playback");
-        sink.append(rodio::Decoder::try_from(file).expect("Can't decode file"));
+        if flag_mode {
+            flag_mode = false;
+            *mode = Some(arg
+                .parse()?);
+            continue;
+        }
+        if flag_output {
+            flag_output = false;
+            *outfile_path = arg.to_string();
+            continue;
+        }
+        if flag_slant {
+            flag_slant = false;
+            *slant_correction = parse_bool(arg).ok_or_else(|| invalid("Invalid slant correction boolean", arg))?;
+            continue;
+        }
+        if flag_free_running {
+            flag_free_running = false;
+            *free_running = parse_bool(arg).ok_or_else(|| invalid("Invalid free-running boolean", arg))?;
+            continue;
+        }
+        if flag_correlate {
+            flag_correlate = false;
+            *correlate = parse_bool(arg).ok_or_else(|| invalid("Invalid correlation boolean", arg))?;
+            continue;
+        }
+        if flag_sample_rate {
+            flag_sample_rate = false;
+            *sample_rate = arg.parse().map_err(|_| invalid("Invalid sample rate", arg))?;
+            if *sample_rate < MIN_SAMPLE_RATE {
+                return Err(invalid(&format!("Sample rate must be at least {} Hz", MIN_SAMPLE_RATE), arg));
+            }
+            continue;
+        }
+        if flag_update {
+            flag_update = false;
+            *update_rows = arg.parse().map_err(|_| invalid("Invalid line count", arg))?;
+            continue;
+        }
 
-        sink.sleep_until_end();
+        match arg {
+            "-h" | "--help" => {
+                print!("{}", helpmsg);
+                std::process::exit(0);
+            }
+            "-m" | "--mode" => {
+                flag_mode = true;
+            }
+            "-o" => {
+                flag_output = true;
+            }
+            "-l" => {
+                flag_slant = true;
+            }
+            "-f" => {
+                flag_free_running = true;
+            }
+            "-x" => {
+                flag_correlate = true;
+            }
+            "-s" | "--sample-rate" => {
+                flag_sample_rate = true;
+            }
+            "-n" => {
+                flag_update = true;
+            }
+            _ => {
+                *infile_path = arg.to_string();
+            }
+        }
     }
-
+    Ok(())
 }
 
-fn parse_args(args: &mut Vec<String>, mode: &mut SSTVMode, volume: &mut f32, sample_rate: &mut u32, infile_path: &mut String, outfile_path: &mut String, calibration: &mut bool, playback: &mut bool) {
-    let helpmsg = format!(r#"Usage: {} infile [options]
+#[allow(clippy::too_many_arguments)]
+fn parse_args(args: &[String], mode: &mut SSTVMode, volume: &mut f32, sample_rate: &mut u32, infile_path: &mut String, outfile_path: &mut String, calibration: &mut bool, playback: &mut bool, fit: &mut Fit, filter: &mut FilterType, sharpen: &mut f32, overlays: &mut Vec<overlay::TextOverlay>, template_path: &mut Option<String>, slot_paths: &mut Vec<(String, String)>, values: &mut HashMap<String, String>, test_pattern: &mut Option<pattern::Pattern>) -> Result<(), Error> {
+    let helpmsg = format!(r#"Usage: {0} infile [options]
+       {0} decode infile.wav [options]   (see {0} decode --help)
+       {0} simulate infile.wav [options]   (see {0} simulate --help)
 Options:
   -h, --help                Display this text
   --version                 Display version information
   -m, --mode <mode>         Specify SSTV mode(default Scottie S1)
   -v, --volume <num>        Specify audio volume percentage(0-100, default 50)
   -s, --sample-rate <num>   Specify audio sample rate(default 44100)
-  -o <filename>             Specify output file name
+  -o <filename>             Specify output file name, .raw or .pcm writes headerless 16-bit PCM
   -c <bool>                 Specify optional calibration tone at start(default true)
-  -p <bool>                 Specify wav audio playback after conversion(default false)
+  -p <bool>                 Specify audio playback after conversion(default false)
+  -a, --fit <fit>           Specify how other aspect ratios are fitted: stretch, letterbox, crop(default stretch)
+  --pad <rrggbb>            Specify letterbox bar colour(default 000000)
+  --focus <x,y>             Specify crop focal point as fractions of the image size, implies crop(default 0.5,0.5)
+  -g, --pattern <pattern>   Specify a test pattern instead of infile: bars, gray, multiburst, grid
+  -r, --filter <filter>     Specify resize filter: nearest, triangle, catmullrom, gaussian, lanczos3(default lanczos3)
+  --sharpen <sigma>         Specify unsharp mask Gaussian sigma in pixels, applied to every picture, 0 for none(default 0)
+
+Overlay options, the given lines are stacked in this order:
+  --callsign <call>         Specify callsign to identify with
+  --report <rsv>            Specify signal report, e.g. "RSV 595"
+  --text <text>             Specify free text, \n starts a new line
+  --timestamp <bool>        Specify UTC date and time line(default false)
+  --text-pos <anchor>       Specify position: tl, t, tr, l, c, r, bl, b, br(default tl)
+  --text-size <num>         Specify pixels per font dot, lines are 9 dots high, 1-64(default 2)
+  --text-color <rrggbb>     Specify text colour(default ffffff)
+  --text-outline <rrggbb>   Specify outline colour or none(default 000000)
+  --text-box <rrggbb>       Specify background box colour or none(default none)
+
+Template options:
+  -t, --template <file>     Specify a TOML or JSON layer template, infile fills its "main" image slot
+  --slot <name>=<file>      Specify the image for a template slot
+  --set <name>=<value>      Specify the text for a template {{name}} placeholder, {{mode}} and {{utc}} are built in, {{{{ is a literal {{
 
 Modes:
    Mode name      Transfer time(s)     Resolution     Speed(lpm)
@@ -557,6 +610,53 @@ Modes:
   Scottie3, S3          55              320x128          140
   Scottie4, S4          36              160x128          216
   ScottieDX, SDX       269              320x256           57
+  PD50                  50              320x256          309
+  PD90                  90              320x256          171
+  PD120                126              640x496          236
+  PD160                161              512x400          149
+  PD180                187              640x496          159
+  PD240                248              640x496          120
+  PD290                289              800x616          128
+  Wraase30, SC2-30      31              320x128          250
+  Wraase60, SC2-60      61              320x256          250
+  Wraase120, SC2-120   122              320x256          126
+  Wraase180, SC2-180   182              320x256           84
+  Pasokon3, P3         203              640x496          147
+  Pasokon5, P5         305              640x496           98
+  Pasokon7, P7         406              640x496           73
+  MP73                  73              320x256          211
+  MP115                115              320x256          133
+  MP140                143              320x256          107
+  MP175                179              320x256           86
+  MR73                  73              320x256          210
+  MR90                  91              320x256          169
+  MR115                115              320x256          133
+  MR140                140              320x256          109
+  MR175                175              320x256           88
+  ML180                180              640x496          165
+  ML240                240              640x496          124
+  ML280                280              640x496          106
+  ML320                320              640x496           93
+  MN73(narrow)          73              320x256          211
+  MN110(narrow)        110              320x256          140
+  MN140(narrow)        140              320x256          109
+  MC110(narrow)        110              320x256          140
+  MC140(narrow)        140              320x256          109
+  MC180(narrow)        180              320x256           85
+  RobotBW8, BW8          8              160x120          896
+  RobotBW12, BW12       12              160x120          600
+  RobotBW24, BW24       24              320x240          600
+  RobotBW36, BW36       36              320x240          400
+  AVT24                 28              128x120          320
+  AVT90                 95              256x240          160
+  AVT94                 99              320x200          128
+  AVT125               130              320x400          192
+  FAX480               128              512x480          224
+
+Exit codes:
+  2  invalid option or mode     3  unreadable input image     4  unreadable input audio
+  5  output write failed        6  no picture decoded         7  audio playback failed
+  8  invalid template           9  internal tone timing error
 "#, args[0]);
     let versionmsg = format!(r#"
 {} Version 1.0.0 20260126
@@ -573,26 +673,58 @@ Modes:
     let mut flag_output = false;
     let mut flag_calibration = false;
     let mut flag_playback = false;
-
-    for arg in args {
+    let mut flag_fit = false;
+    let mut flag_pad = false;
+    let mut flag_focus = false;
+    let mut flag_filter = false;
+    let mut flag_sharpen = false;
+    let mut flag_callsign = false;
+    let mut flag_report = false;
+    let mut flag_text = false;
+    let mut flag_timestamp = false;
+    let mut flag_text_pos = false;
+    let mut flag_text_size = false;
+    let mut flag_text_color = false;
+    let mut flag_text_outline = false;
+    let mut flag_text_box = false;
+    let mut flag_template = false;
+    let mut flag_slot = false;
+    let mut flag_set = false;
+    let mut flag_pattern = false;
+
+    //lines of the overlay, joined once every option is read
+    let mut callsign: Option<String> = None;
+    let mut report: Option<String> = None;
+    let mut text: Option<String> = None;
+    let mut timestamp = false;
+    let mut style = overlay::TextOverlay::new("");
+
+    //combined into fit once every option is read, so their order doesn't matter
+    let mut fit_name: Option<String> = None;
+    let mut pad = image::Rgb([0, 0, 0]);
+    let mut focus: Option<(f32, f32)> = None;
+
+    for arg in &args[1..] {
         let arg: &str = arg;
 
         if flag_mode {
             flag_mode = false;
             *mode = arg
-                .parse()
-                .expect("Invalid SSTV Mode");
+                .parse()?;
             continue;
         }
         if flag_volume {
             flag_volume = false;
-            *volume = arg.parse::<f32>().expect("Invalid Volume") / 100.0;
+            *volume = arg.parse::<f32>().map_err(|_| invalid("Invalid Volume", arg))? / 100.0;
             *volume = (*volume).clamp(0.0, 100.0);
             continue;
         }
         if flag_samplerate {
             flag_samplerate = false;
-            *sample_rate = arg.parse::<u32>().expect("Invalid Sample Rate");
+            *sample_rate = arg.parse::<u32>().map_err(|_| invalid("Invalid Sample Rate", arg))?;
+            if *sample_rate < MIN_SAMPLE_RATE {
+                return Err(invalid(&format!("Sample rate must be at least {} Hz", MIN_SAMPLE_RATE), arg));
+            }
             continue;
         }
         if flag_output {
@@ -602,32 +734,104 @@ Modes:
         }
         if flag_calibration {
             flag_calibration = false;
-            *calibration = match arg.to_lowercase().as_str() {
-                "true" | "yes" | "t" | "yerp" | "yuhuh" | "please" | "pwease" | "ya" | "yer" | "ye" | "y" | "da" => {
-                    true
-                }
-                "false" | "no" | "n" | "f" | "nerp" | "nuhuh" | "nu" | "ner" => {
-                    false
-                }
-                _ => {
-                    panic!("Invalid calibration boolean");
-                }
-            };
+            *calibration = parse_bool(arg).ok_or_else(|| invalid("Invalid calibration boolean", arg))?;
             continue;
         }
         if flag_playback {
             flag_playback = false;
-            *playback = match arg.to_lowercase().as_str() {
-                "true" | "yes" | "t" | "yerp" | "yuhuh" | "please" | "pwease" | "ya" | "yer" | "ye" | "y" | "da" => {
-                    true
-                }
-                "false" | "no" | "n" | "f" | "nerp" | "nuhuh" | "nu" | "ner" => {
-                    false
-                }
-                _ => {
-                    panic!("Invalid calibration boolean");
-                }
-            };
+            *playback = parse_bool(arg).ok_or_else(|| invalid("Invalid playback boolean", arg))?;
+            continue;
+        }
+        if flag_fit {
+            flag_fit = false;
+            fit_name = Some(arg.to_lowercase());
+            continue;
+        }
+        if flag_pad {
+            flag_pad = false;
+            pad = overlay::parse_color(arg).ok_or_else(|| invalid("Invalid pad colour", arg))?;
+            continue;
+        }
+        if flag_focus {
+            flag_focus = false;
+            focus = Some(parse_point(arg).ok_or_else(|| invalid("Invalid focal point", arg))?);
+            continue;
+        }
+        if flag_filter {
+            flag_filter = false;
+            *filter = parse_filter(arg).ok_or_else(|| invalid("Invalid resize filter", arg))?;
+            continue;
+        }
+        if flag_sharpen {
+            flag_sharpen = false;
+            *sharpen = arg.parse::<f32>().map_err(|_| invalid("Invalid sharpen sigma", arg))?.max(0.0);
+            continue;
+        }
+        if flag_callsign {
+            flag_callsign = false;
+            callsign = Some(arg.to_string());
+            continue;
+        }
+        if flag_report {
+            flag_report = false;
+            report = Some(arg.to_string());
+            continue;
+        }
+        if flag_text {
+            flag_text = false;
+            text = Some(arg.replace("\\n", "\n"));
+            continue;
+        }
+        if flag_timestamp {
+            flag_timestamp = false;
+            timestamp = parse_bool(arg).ok_or_else(|| invalid("Invalid timestamp boolean", arg))?;
+            continue;
+        }
+        if flag_text_pos {
+            flag_text_pos = false;
+            style.anchor = arg.parse().map_err(|_| invalid("Invalid text position", arg))?;
+            continue;
+        }
+        if flag_text_size {
+            flag_text_size = false;
+            style.scale = arg.parse::<u32>().map_err(|_| invalid("Invalid text size", arg))?.clamp(1, overlay::MAX_SCALE);
+            continue;
+        }
+        if flag_text_color {
+            flag_text_color = false;
+            style.color = overlay::parse_color(arg).ok_or_else(|| invalid("Invalid text colour", arg))?;
+            continue;
+        }
+        if flag_text_outline {
+            flag_text_outline = false;
+            style.outline = parse_optional_color(arg).ok_or_else(|| invalid("Invalid outline colour", arg))?;
+            continue;
+        }
+        if flag_text_box {
+            flag_text_box = false;
+            style.background = parse_optional_color(arg).ok_or_else(|| invalid("Invalid box colour", arg))?;
+            continue;
+        }
+        if flag_template {
+            flag_template = false;
+            *template_path = Some(arg.to_string());
+            continue;
+        }
+        if flag_slot {
+            flag_slot = false;
+            let (name, path) = arg.split_once('=').ok_or_else(|| invalid("Invalid slot, expected name=file", arg))?;
+            slot_paths.push((name.to_string(), path.to_string()));
+            continue;
+        }
+        if flag_set {
+            flag_set = false;
+            let (name, value) = arg.split_once('=').ok_or_else(|| invalid("Invalid placeholder, expected name=value", arg))?;
+            values.insert(name.to_string(), value.to_string());
+            continue;
+        }
+        if flag_pattern {
+            flag_pattern = false;
+            *test_pattern = Some(arg.parse()?);
             continue;
         }
 
@@ -658,130 +862,131 @@ Modes:
             "-p" => {
                 flag_playback = true;
             }
+            "-a" | "--fit" => {
+                flag_fit = true;
+            }
+            "--pad" => {
+                flag_pad = true;
+            }
+            "--focus" => {
+                flag_focus = true;
+            }
+            "-r" | "--filter" => {
+                flag_filter = true;
+            }
+            "--sharpen" => {
+                flag_sharpen = true;
+            }
+            "--callsign" => {
+                flag_callsign = true;
+            }
+            "--report" => {
+                flag_report = true;
+            }
+            "--text" => {
+                flag_text = true;
+            }
+            "--timestamp" => {
+                flag_timestamp = true;
+            }
+            "--text-pos" => {
+                flag_text_pos = true;
+            }
+            "--text-size" => {
+                flag_text_size = true;
+            }
+            "--text-color" | "--text-colour" => {
+                flag_text_color = true;
+            }
+            "--text-outline" => {
+                flag_text_outline = true;
+            }
+            "--text-box" => {
+                flag_text_box = true;
+            }
+            "-t" | "--template" => {
+                flag_template = true;
+            }
+            "--slot" => {
+                flag_slot = true;
+            }
+            "--set" => {
+                flag_set = true;
+            }
+            "-g" | "--pattern" => {
+                flag_pattern = true;
+            }
             _ => {
                 *infile_path = arg.to_string();
             }
         }
     }
 
-    
-}
-
-fn rgb_to_ycrcb(red: u8, green: u8, blue: u8) -> (f32, f32, f32) {
-    let r: f32 = (red as f32)/255.0;
-    let g: f32 = (green as f32)/255.0;
-    let b: f32 = (blue as f32)/255.0;
-
-    let y = 0.299*r + 0.587*g + 0.114*b;
-    let cr = r - y;
-    let cb = b - y;
-
-    (y, cr, cb)
-}
+    //a focal point alone is enough to ask for a crop
+    *fit = match (fit_name.as_deref(), focus) {
+        (None, Some(focus)) => Fit::Crop {focus},
+        (None, None) | (Some("stretch"), _) => Fit::Stretch,
+        (Some("letterbox") | Some("pillarbox") | Some("pad"), _) => Fit::Letterbox {pad},
+        (Some("crop"), focus) => Fit::Crop {focus: focus.unwrap_or((0.5, 0.5))},
+        (Some(name), _) => return Err(invalid("Invalid fit", name)),
+    };
 
-fn write_vis<W: std::io::Write + std::io::Seek>(
-    writer: &mut hound::WavWriter<W>,
-    osc: &mut Oscillator,
-    vis_code: u8,
-    incl_calibration: bool
-) {
-
-    if incl_calibration {
-        emit_tone(writer, osc, 1900.0, 100.0);
-        emit_tone(writer, osc, 1500.0, 100.0);
-        emit_tone(writer, osc, 1900.0, 100.0);
-        emit_tone(writer, osc, 1500.0, 100.0);
-
-        emit_tone(writer, osc, 2300.0, 100.0);
-        emit_tone(writer, osc, 1500.0, 100.0);
-        emit_tone(writer, osc, 2300.0, 100.0);
-        emit_tone(writer, osc, 1500.0, 100.0);
+    //the pattern takes the place of infile, so only one of them may be given
+    if test_pattern.is_some() && !infile_path.is_empty() {
+        return Err(invalid("A test pattern can't be combined with an input image", infile_path));
     }
 
+    let timestamp = timestamp.then(|| overlay::utc_timestamp(std::time::SystemTime::now()));
+    let lines: Vec<String> = [callsign, report, text, timestamp].into_iter().flatten().collect();
+    if !lines.is_empty() {
+        style.text = lines.join("\n");
+        overlays.push(style);
+    }
 
+    Ok(())
+}
 
-    const VIS_LEADER_MS: f32 = 300.0;
-    const VIS_BREAK_MS: f32 = 10.0;
-    const VIS_BIT_MS: f32 = 30.0;
-
-    const VIS_LEADER_HZ: f32 = 1900.0;
-    const VIS_BIT_1_HZ: f32 = 1100.0;
-    const VIS_BIT_0_HZ: f32 = 1300.0;
-    const VIS_BIT_N_HZ: f32 = 1200.0;
-    //write VIS
-    println!("Writing VIS header");
-    emit_tone(writer, osc, VIS_LEADER_HZ, VIS_LEADER_MS);
-    emit_tone(writer, osc, VIS_BIT_N_HZ, VIS_BREAK_MS);
-    emit_tone(writer, osc, VIS_LEADER_HZ, VIS_LEADER_MS);
-
-    //start bit
-    emit_tone(writer, osc, VIS_BIT_N_HZ, VIS_BIT_MS);
+//rejected option value, named in the error message
+fn invalid(what: &str, arg: &str) -> Error {
+    Error::InvalidArgument(format!("{}: {}", what, arg))
+}
 
-    let mut vis_code = vis_code;
-    let mut parity = false;
-    for _ in 0..7 {
-        let bit = vis_code & 1;
-        if bit == 1{
-            emit_tone(writer, osc, VIS_BIT_1_HZ, VIS_BIT_MS);
-            parity = !parity;
+fn parse_bool(arg: &str) -> Option<bool> {
+    match arg.to_lowercase().as_str() {
+        "true" | "yes" | "t" | "yerp" | "yuhuh" | "please" | "pwease" | "ya" | "yer" | "ye" | "y" | "da" => {
+            Some(true)
         }
-        else {
-            emit_tone(writer, osc, VIS_BIT_0_HZ, VIS_BIT_MS);
+        "false" | "no" | "n" | "f" | "nerp" | "nuhuh" | "nu" | "ner" => {
+            Some(false)
+        }
+        _ => {
+            None
         }
-        vis_code >>= 1;
     }
-    //parity bit
-    emit_tone(writer, osc, if parity {VIS_BIT_1_HZ} else {VIS_BIT_0_HZ}, VIS_BIT_MS);
-    //stop bit
-    emit_tone(writer, osc, VIS_BIT_N_HZ, VIS_BIT_MS);
 }
 
-fn emit_tone<W: std::io::Write + std::io::Seek>(
-    writer: &mut hound::WavWriter<W>,
-    osc: &mut Oscillator,
-    freq_hz: f32,
-    duration_ms: f32,
-) {
-    if duration_ms == 0.0 {
-        println!("Warning: 0 ms emit tone");
-    }
-    if duration_ms < 0.0 {
-        panic!("Invalid duration: {}", duration_ms);
-    }
-    if let Err(e) = _emit_tone(writer, osc, freq_hz, duration_ms) {
-        eprintln!("Failed to write tone: {}", e);
+fn parse_filter(arg: &str) -> Option<FilterType> {
+    match arg.to_lowercase().as_str() {
+        "nearest" => Some(FilterType::Nearest),
+        "triangle" | "bilinear" => Some(FilterType::Triangle),
+        "catmullrom" | "catmull-rom" | "cubic" => Some(FilterType::CatmullRom),
+        "gaussian" => Some(FilterType::Gaussian),
+        "lanczos3" | "lanczos" => Some(FilterType::Lanczos3),
+        _ => None,
     }
 }
 
-fn _emit_tone<W: std::io::Write + std::io::Seek>(
-    writer: &mut hound::WavWriter<W>,
-    osc: &mut Oscillator,
-    freq_hz: f32,
-    duration_ms: f32,
-) -> Result<(), hound::Error> {
-    let sr = osc.sample_rate as f32;
-    let exact_samples = duration_ms * sr / 1000.0;
-    let total_samples = exact_samples + osc.frac_samples;
-    let samples_to_write = total_samples.floor() as usize;
-    osc.frac_samples = total_samples - (samples_to_write as f32);
-
-    let is_silence = freq_hz <= 0.0;
-    let phase_inc = if is_silence { 0.0 } else { 2.0 * PI * freq_hz / sr };
-    let amp_scale = i16::MAX as f32 * osc.amplitude;
-
-    for _ in 0..samples_to_write {
-        let sample_f = if is_silence { 0.0 } else { osc.phase.sin() * amp_scale };
-        let s_clamped = sample_f.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
-
-        writer.write_sample(s_clamped)?;
-
-        if !is_silence {
-            osc.phase += phase_inc;
-            if osc.phase >= 2.0 * PI {
-                osc.phase -= 2.0 * PI;
-            }
-        }
+//rrggbb or none
+fn parse_optional_color(arg: &str) -> Option<Option<image::Rgb<u8>>> {
+    if arg.eq_ignore_ascii_case("none") {
+        return Some(None);
     }
+    overlay::parse_color(arg).map(Some)
+}
 
-    Ok(())
+//x,y with both in 0.0-1.0
+fn parse_point(arg: &str) -> Option<(f32, f32)> {
+    let (x, y) = arg.split_once(',')?;
+    let (x, y): (f32, f32) = (x.trim().parse().ok()?, y.trim().parse().ok()?);
+    ((0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y)).then_some((x, y))
 }
@@ -0,0 +1,426 @@
+use image::{Rgb, RgbImage};
+
+//5x8 bitmap font for ASCII 0x20-0x7E, one byte per column, bit 0 at the top
+//rows 0-6 hold capitals and digits, row 7 the descenders
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 8;
+//one blank column between characters and one blank row between lines
+const ADVANCE: u32 = GLYPH_WIDTH + 1;
+const LINE_HEIGHT: u32 = GLYPH_HEIGHT + 1;
+//largest dot size, one glyph is then 320x512 pixels, bigger than any mode
+pub const MAX_SCALE: u32 = 64;
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x56, 0x20, 0x50], // &
+    [0x00, 0x08, 0x07, 0x03, 0x00], // '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // )
+    [0x2A, 0x1C, 0x7F, 0x1C, 0x2A], // *
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // +
+    [0x00, 0x80, 0x70, 0x30, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x00, 0x60, 0x60, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // 0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // 1
+    [0x72, 0x49, 0x49, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x49, 0x4D, 0x33], // 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x31], // 6
+    [0x41, 0x21, 0x11, 0x09, 0x07], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x46, 0x49, 0x49, 0x29, 0x1E], // 9
+    [0x00, 0x00, 0x14, 0x00, 0x00], // :
+    [0x00, 0x40, 0x34, 0x00, 0x00], // ;
+    [0x00, 0x08, 0x14, 0x22, 0x41], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x59, 0x09, 0x06], // ?
+    [0x3E, 0x41, 0x5D, 0x59, 0x4E], // @
+    [0x7C, 0x12, 0x11, 0x12, 0x7C], // A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7F, 0x41, 0x41, 0x41, 0x3E], // D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3E, 0x41, 0x41, 0x51, 0x73], // G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7F, 0x02, 0x1C, 0x02, 0x7F], // M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // R
+    [0x26, 0x49, 0x49, 0x49, 0x32], // S
+    [0x03, 0x01, 0x7F, 0x01, 0x03], // T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // V
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x03, 0x04, 0x78, 0x04, 0x03], // Y
+    [0x61, 0x59, 0x49, 0x4D, 0x43], // Z
+    [0x00, 0x7F, 0x41, 0x41, 0x41], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // backslash
+    [0x00, 0x41, 0x41, 0x41, 0x7F], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x03, 0x07, 0x08, 0x00], // `
+    [0x20, 0x54, 0x54, 0x78, 0x40], // a
+    [0x7F, 0x28, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x28], // c
+    [0x38, 0x44, 0x44, 0x28, 0x7F], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x00, 0x08, 0x7E, 0x09, 0x02], // f
+    [0x18, 0xA4, 0xA4, 0x9C, 0x78], // g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // i
+    [0x20, 0x40, 0x40, 0x3D, 0x00], // j
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // l
+    [0x7C, 0x04, 0x78, 0x04, 0x78], // m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0xFC, 0x18, 0x24, 0x24, 0x18], // p
+    [0x18, 0x24, 0x24, 0x18, 0xFC], // q
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x24], // s
+    [0x04, 0x04, 0x3F, 0x44, 0x24], // t
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // u
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // v
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x4C, 0x90, 0x90, 0x90, 0x7C], // y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x77, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x02, 0x01, 0x02, 0x04, 0x02], // ~
+];
+
+
+//corner, edge or centre of the image the text is placed against
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Centre,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl std::str::FromStr for Anchor {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "topleft" | "tl" => Ok(Anchor::TopLeft),
+            "top" | "t" => Ok(Anchor::Top),
+            "topright" | "tr" => Ok(Anchor::TopRight),
+            "left" | "l" => Ok(Anchor::Left),
+            "centre" | "center" | "c" => Ok(Anchor::Centre),
+            "right" | "r" => Ok(Anchor::Right),
+            "bottomleft" | "bl" => Ok(Anchor::BottomLeft),
+            "bottom" | "b" => Ok(Anchor::Bottom),
+            "bottomright" | "br" => Ok(Anchor::BottomRight),
+            _ => Err(()),
+        }
+    }
+}
+
+//block of text drawn onto the picture at the mode's resolution, lines split on '\n'
+//lines are aligned to the anchor's side, centred for Top, Centre and Bottom
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextOverlay {
+    pub text: String,
+    pub anchor: Anchor,
+    //distance from the anchored edges in pixels
+    pub margin: u32,
+    //pixels per font dot, 1 gives 8 pixel high lines, clamped to 1..=MAX_SCALE
+    pub scale: u32,
+    pub color: Rgb<u8>,
+    pub outline: Option<Rgb<u8>>,
+    //box behind the whole block
+    pub background: Option<Rgb<u8>>,
+}
+
+impl TextOverlay {
+    //white text with a black outline in the top left corner
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            anchor: Anchor::TopLeft,
+            margin: 4,
+            scale: 2,
+            color: Rgb([255, 255, 255]),
+            outline: Some(Rgb([0, 0, 0])),
+            background: None,
+        }
+    }
+
+    //size of the drawn block, background padding included
+    pub fn size(&self) -> (u32, u32) {
+        let lines: Vec<&str> = self.text.lines().collect();
+        let columns = lines.iter().map(|l| l.chars().count() as u64).max().unwrap_or(0);
+        let scale = self.dot() as u64;
+        //u64 so long text at a large scale saturates instead of overflowing
+        let width = (columns * ADVANCE as u64).saturating_sub(1) * scale;
+        let height = (lines.len() as u64 * LINE_HEIGHT as u64).saturating_sub(1) * scale;
+        let pad = 2 * self.padding() as u64;
+        ((width + pad).min(u32::MAX as u64) as u32, (height + pad).min(u32::MAX as u64) as u32)
+    }
+
+    fn dot(&self) -> u32 {
+        self.scale.clamp(1, MAX_SCALE)
+    }
+
+    fn padding(&self) -> u32 {
+        //the outline would otherwise touch the image edge or the box border
+        if self.background.is_some() || self.outline.is_some() {self.dot()} else {0}
+    }
+
+    pub fn draw(&self, image: &mut RgbImage) {
+        let (width, height) = image.dimensions();
+        self.draw_in(image, 0, 0, width, height);
+    }
+
+    //anchors the text to the given area instead of the whole image
+    pub fn draw_in(&self, image: &mut RgbImage, x: i64, y: i64, width: u32, height: u32) {
+        let scale = self.dot();
+        let (block_w, block_h) = self.size();
+        let margin = self.margin as i64;
+
+        let left = x + match self.anchor {
+            Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => margin,
+            Anchor::Top | Anchor::Centre | Anchor::Bottom => (width as i64 - block_w as i64) / 2,
+            _ => width as i64 - block_w as i64 - margin,
+        };
+        let top = y + match self.anchor {
+            Anchor::TopLeft | Anchor::Top | Anchor::TopRight => margin,
+            Anchor::Left | Anchor::Centre | Anchor::Right => (height as i64 - block_h as i64) / 2,
+            _ => height as i64 - block_h as i64 - margin,
+        };
+
+        if let Some(background) = self.background {
+            fill_rect(image, left, top, block_w, block_h, background);
+        }
+
+        //glyph dots as rectangles, so the outline and fill passes share them
+        let pad = self.padding() as i64;
+        let inner_w = block_w as i64 - 2 * pad;
+        let mut dots = Vec::new();
+        for (row, line) in self.text.lines().enumerate() {
+            let line_w = (line.chars().count() as i64 * ADVANCE as i64 - 1).max(0) * scale as i64;
+            let x0 = left + pad + match self.anchor {
+                Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => 0,
+                Anchor::Top | Anchor::Centre | Anchor::Bottom => (inner_w - line_w) / 2,
+                _ => inner_w - line_w,
+            };
+            let y0 = top + pad + row as i64 * LINE_HEIGHT as i64 * scale as i64;
+            for (i, c) in line.chars().enumerate() {
+                let glyph = glyph(c);
+                for (col, bits) in glyph.iter().enumerate() {
+                    for bit in 0..GLYPH_HEIGHT {
+                        if bits >> bit & 1 == 1 {
+                            let x = x0 + (i as i64 * ADVANCE as i64 + col as i64) * scale as i64;
+                            let y = y0 + bit as i64 * scale as i64;
+                            dots.push((x, y));
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(outline) = self.outline {
+            let t = (scale / 2).max(1) as i64;
+            for &(x, y) in &dots {
+                fill_rect(image, x - t, y - t, scale + 2 * t as u32, scale + 2 * t as u32, outline);
+            }
+        }
+        for &(x, y) in &dots {
+            fill_rect(image, x, y, scale, scale, self.color);
+        }
+    }
+}
+
+//characters outside the font show as '?'
+fn glyph(c: char) -> &'static [u8; 5] {
+    match c {
+        ' '..='~' => &FONT[c as usize - 0x20],
+        _ => &FONT['?' as usize - 0x20],
+    }
+}
+
+//rrggbb hex colour, with or without a leading #
+pub fn parse_color(s: &str) -> Option<Rgb<u8>> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    //from_str_radix alone would also take a sign, e.g. "+12345"
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some(Rgb([(value >> 16) as u8, (value >> 8) as u8, value as u8]))
+}
+
+//clipped to the image
+pub(crate) fn fill_rect(image: &mut RgbImage, x: i64, y: i64, width: u32, height: u32, color: Rgb<u8>) {
+    let (w, h) = image.dimensions();
+    let x0 = x.clamp(0, w as i64) as u32;
+    let y0 = y.clamp(0, h as i64) as u32;
+    let x1 = (x + width as i64).clamp(0, w as i64) as u32;
+    let y1 = (y + height as i64).clamp(0, h as i64) as u32;
+    for py in y0..y1 {
+        for px in x0..x1 {
+            image.put_pixel(px, py, color);
+        }
+    }
+}
+
+//UTC date and time as "2026-01-26 14:05Z", the usual way to stamp a transmission
+pub fn utc_timestamp(time: std::time::SystemTime) -> String {
+    let secs = time.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86400, secs % 86400);
+    //civil date from days since 1970-01-01, Howard Hinnant's algorithm
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 {mp + 3} else {mp - 9};
+    let year = yoe + era * 400 + if month <= 2 {1} else {0};
+    format!("{:04}-{:02}-{:02} {:02}:{:02}Z", year, month, day, rem / 3600, rem % 3600 / 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+    const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+
+    fn plain(text: &str, scale: u32) -> TextOverlay {
+        TextOverlay {margin: 0, scale, outline: None, ..TextOverlay::new(text)}
+    }
+
+    //bounding box of every pixel that is not black, (x0, y0, x1, y1) exclusive
+    fn bounds(image: &RgbImage) -> Option<(u32, u32, u32, u32)> {
+        image.enumerate_pixels().filter(|(_, _, p)| **p != BLACK).fold(None, |b, (x, y, _)| {
+            let (x0, y0, x1, y1) = b.unwrap_or((x, y, x + 1, y + 1));
+            Some((x0.min(x), y0.min(y), x1.max(x + 1), y1.max(y + 1)))
+        })
+    }
+
+    #[test]
+    fn dots_land_on_the_glyph_grid() {
+        //'|' is column 2 with rows 0-2 and 4-6 set
+        let mut image = RgbImage::new(40, 40);
+        plain("|", 3).draw(&mut image);
+        assert_eq!(*image.get_pixel(6, 0), WHITE);
+        assert_eq!(*image.get_pixel(8, 8), WHITE);
+        assert_eq!(*image.get_pixel(6, 9), BLACK);
+        assert_eq!(*image.get_pixel(6, 12), WHITE);
+        assert_eq!(*image.get_pixel(5, 0), BLACK);
+        assert_eq!(*image.get_pixel(9, 0), BLACK);
+        assert_eq!(bounds(&image), Some((6, 0, 9, 21)));
+
+        //second character one advance along, second line one line height down
+        let mut image = RgbImage::new(40, 40);
+        plain(" |\n|", 1).draw(&mut image);
+        assert_eq!(*image.get_pixel(8, 0), WHITE);
+        assert_eq!(*image.get_pixel(2, 9), WHITE);
+        assert_eq!(*image.get_pixel(2, 0), BLACK);
+    }
+
+    #[test]
+    fn block_sits_against_each_anchor() {
+        //5 dots across and 8 down plus 1 padding dot each side, at 2 pixels per dot
+        let text = TextOverlay {margin: 4, background: Some(Rgb([0, 0, 255])), ..plain("X", 2)};
+        assert_eq!(text.size(), (14, 20));
+        let cases = [
+            ("tl", 4, 4), ("t", 43, 4), ("tr", 82, 4),
+            ("l", 4, 30), ("c", 43, 30), ("r", 82, 30),
+            ("bl", 4, 56), ("b", 43, 56), ("br", 82, 56),
+        ];
+        for (anchor, x, y) in cases {
+            let mut image = RgbImage::new(100, 80);
+            TextOverlay {anchor: anchor.parse().unwrap(), ..text.clone()}.draw(&mut image);
+            assert_eq!(bounds(&image), Some((x, y, x + 14, y + 20)), "{}", anchor);
+        }
+
+        //draw_in anchors to the given area
+        let mut image = RgbImage::new(100, 80);
+        TextOverlay {anchor: Anchor::BottomRight, ..text}.draw_in(&mut image, 10, 20, 50, 40);
+        assert_eq!(bounds(&image), Some((42, 36, 56, 56)));
+    }
+
+    #[test]
+    fn lines_align_to_the_anchored_side() {
+        let second_line = |anchor: Anchor| {
+            let mut image = RgbImage::new(60, 30);
+            TextOverlay {anchor, ..plain("|||\n|", 1)}.draw(&mut image);
+            (0..60).find(|&x| *image.get_pixel(x, 9) != BLACK)
+        };
+        assert_eq!(second_line(Anchor::TopLeft), Some(2));
+        assert_eq!(second_line(Anchor::Top), Some(2 + (60 - 17) / 2 + 6));
+        assert_eq!(second_line(Anchor::TopRight), Some(60 - 17 + 12 + 2));
+    }
+
+    #[test]
+    fn anchor_names() {
+        assert_eq!("br".parse(), Ok(Anchor::BottomRight));
+        assert_eq!("Top-Left".parse(), Ok(Anchor::TopLeft));
+        assert_eq!("bottom_left".parse(), Ok(Anchor::BottomLeft));
+        assert_eq!("center".parse(), Ok(Anchor::Centre));
+        assert_eq!("middle".parse::<Anchor>(), Err(()));
+    }
+
+    #[test]
+    fn huge_scale_is_clamped() {
+        let text = TextOverlay {scale: u32::MAX, ..TextOverlay::new("CQ CQ\nDE TEST")};
+        let (width, height) = text.size();
+        assert_eq!(width, (7 * ADVANCE - 1) * MAX_SCALE + 2 * MAX_SCALE);
+        assert_eq!(height, (2 * LINE_HEIGHT - 1) * MAX_SCALE + 2 * MAX_SCALE);
+        let mut image = RgbImage::new(320, 256);
+        text.draw(&mut image);
+
+        let long = TextOverlay {scale: MAX_SCALE, ..TextOverlay::new(&"X".repeat(20_000_000))};
+        assert_eq!(long.size().0, u32::MAX);
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("#ff8000"), Some(Rgb([255, 128, 0])));
+        assert_eq!(parse_color("0000FF"), Some(Rgb([0, 0, 255])));
+        assert_eq!(parse_color("fff"), None);
+        assert_eq!(parse_color("gg0000"), None);
+        assert_eq!(parse_color("#+12345"), None);
+        assert_eq!(parse_color("-12345"), None);
+    }
+
+    #[test]
+    fn timestamps() {
+        let at = |secs| utc_timestamp(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(at(0), "1970-01-01 00:00Z");
+        assert_eq!(at(946684799), "1999-12-31 23:59Z");
+        assert_eq!(at(951827696), "2000-02-29 12:34Z");
+        assert_eq!(at(1769436300), "2026-01-26 14:05Z");
+        assert_eq!(at(4107542400), "2100-03-01 00:00Z");
+        assert_eq!(utc_timestamp(UNIX_EPOCH - Duration::from_secs(60)), "1970-01-01 00:00Z");
+    }
+}
@@ -0,0 +1,197 @@
+use std::f64::consts::PI;
+
+use image::{Rgb, RgbImage};
+
+use crate::{Error, SSTVMode};
+
+//standard alignment pictures, drawn at the mode's resolution so no resize blurs them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    //SMPTE colour bars with -I, +Q and PLUGE
+    Bars,
+    //16 grey steps from black to white
+    Grayscale,
+    //sine bursts of rising frequency above a converging line wedge
+    Multiburst,
+    //crosshatch of square cells with a centre circle
+    Grid,
+}
+
+//75% bars, black carries NTSC setup so the PLUGE below it shows
+const BAR_75: u8 = 191;
+const BLACK: u8 = 19;
+//burst frequencies in cycles per pixel, 0.5 is the image's own limit
+const BURSTS: [f64; 6] = [0.05, 0.1, 0.15, 0.2, 0.3, 0.4];
+
+impl std::str::FromStr for Pattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bars" | "smpte" | "colorbars" | "colourbars" => Ok(Pattern::Bars),
+            "gray" | "grey" | "grayscale" | "greyscale" | "steps" => Ok(Pattern::Grayscale),
+            "multiburst" | "burst" | "wedge" | "resolution" => Ok(Pattern::Multiburst),
+            "grid" | "crosshatch" => Ok(Pattern::Grid),
+            _ => Err(Error::InvalidArgument(format!("Unknown test pattern: {}", s))),
+        }
+    }
+}
+
+impl Pattern {
+    pub fn render(&self, mode: SSTVMode) -> RgbImage {
+        let (width, height) = mode.resolution();
+        match self {
+            Pattern::Bars => bars(width, height),
+            Pattern::Grayscale => RgbImage::from_fn(width, height, |x, _| {
+                let v = (x * 16 / width) as u8 * 17;
+                Rgb([v, v, v])
+            }),
+            Pattern::Multiburst => multiburst(width, height),
+            Pattern::Grid => grid(width, height),
+        }
+    }
+}
+
+fn bars(width: u32, height: u32) -> RgbImage {
+    let (hi, lo, b) = (BAR_75, 0, BLACK);
+    let top = [[hi, hi, hi], [hi, hi, lo], [lo, hi, hi], [lo, hi, lo], [hi, lo, hi], [hi, lo, lo], [lo, lo, hi]];
+    //reverse order castellations with black between them, for chroma and hue checks against the bars above
+    let middle = [[lo, lo, hi], [b, b, b], [hi, lo, hi], [b, b, b], [lo, hi, hi], [b, b, b], [hi, hi, hi]];
+    let bar = width as f64 / 7.0;
+    RgbImage::from_fn(width, height, |x, y| {
+        let column = ((x as f64 / bar) as usize).min(6);
+        let rgb = if y < height * 2 / 3 {
+            top[column]
+        }
+        else if y < height * 3 / 4 {
+            middle[column]
+        }
+        else {
+            //-I, 100% white and +Q under the first five bars, then PLUGE below the red bar
+            let pos = x as f64 / bar;
+            match pos {
+                p if p < 1.25 => [0, 33, 76],
+                p if p < 2.5 => [255, 255, 255],
+                p if p < 3.75 => [50, 0, 106],
+                p if p < 5.0 => [b, b, b],
+                p if p < 5.0 + 1.0 / 3.0 => [9, 9, 9],
+                p if p < 5.0 + 2.0 / 3.0 => [b, b, b],
+                p if p < 6.0 => [29, 29, 29],
+                _ => [b, b, b],
+            }
+        };
+        Rgb(rgb)
+    })
+}
+
+fn multiburst(width: u32, height: u32) -> RgbImage {
+    let burst_bottom = height * 3 / 5;
+    //white and black reference flag, then one packet per frequency
+    let flag = width / 8;
+    let packet = (width - flag) / BURSTS.len() as u32;
+    RgbImage::from_fn(width, height, |x, y| {
+        let v = if y < burst_bottom {
+            if x < flag {
+                if x < flag / 2 {1.0} else {0.0}
+            }
+            else {
+                let index = (((x - flag) / packet.max(1)) as usize).min(BURSTS.len() - 1);
+                let start = flag + index as u32 * packet;
+                //a grey gap either side keeps the packets apart
+                let edge = packet / 10;
+                if x < start + edge || x >= start + packet - edge {
+                    0.5
+                }
+                else {
+                    0.5 + 0.5 * (2.0 * PI * BURSTS[index] * (x - start - edge) as f64).sin()
+                }
+            }
+        }
+        else {
+            //lines from the left edge converging to ten times their density at the right, vertical detail fades out where they merge
+            let v = (y - burst_bottom) as f64 / (height - burst_bottom) as f64 - 0.5;
+            let u = x as f64 / width as f64;
+            let line = (v * 16.0 / (1.0 - 0.9 * u)).floor() as i64;
+            if line.rem_euclid(2) == 0 {1.0} else {0.0}
+        };
+        let v = (v * 255.0).round() as u8;
+        Rgb([v, v, v])
+    })
+}
+
+fn grid(width: u32, height: u32) -> RgbImage {
+    //16 square cells across, as many rows as fit, centred vertically
+    let cell = (width / 16).max(2);
+    let offset_y = (height % cell) / 2;
+    let (cx, cy) = (width as f64 / 2.0, height as f64 / 2.0);
+    let radius = width.min(height) as f64 * 0.4;
+    RgbImage::from_fn(width, height, |x, y| {
+        let on_grid = x.is_multiple_of(cell) || x == width - 1 || (y + cell - offset_y).is_multiple_of(cell) || y == height - 1;
+        let r = ((x as f64 + 0.5 - cx).powi(2) + (y as f64 + 0.5 - cy).powi(2)).sqrt();
+        let on_circle = (r - radius).abs() < 0.75;
+        if on_grid || on_circle {Rgb([255, 255, 255])} else {Rgb([0, 0, 0])}
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATTERNS: [Pattern; 4] = [Pattern::Bars, Pattern::Grayscale, Pattern::Multiburst, Pattern::Grid];
+
+    #[test]
+    fn drawn_at_every_mode_resolution() {
+        for mode in SSTVMode::ALL {
+            for pattern in PATTERNS {
+                assert_eq!(pattern.render(mode).dimensions(), mode.resolution(), "{:?} {:?}", pattern, mode);
+            }
+        }
+    }
+
+    #[test]
+    fn bar_colors() {
+        let image = Pattern::Bars.render(SSTVMode::M1);
+        let (hi, lo, b) = (BAR_75, 0, BLACK);
+        let centre = |column: f64| (column * 320.0 / 7.0) as u32;
+        let top = [[hi, hi, hi], [hi, hi, lo], [lo, hi, hi], [lo, hi, lo], [hi, lo, hi], [hi, lo, lo], [lo, lo, hi]];
+        for (i, rgb) in top.iter().enumerate() {
+            assert_eq!(image.get_pixel(centre(i as f64 + 0.5), 20).0, *rgb, "bar {}", i);
+        }
+        //castellations under white and yellow are blue and black
+        assert_eq!(image.get_pixel(centre(0.5), 180).0, [lo, lo, hi]);
+        assert_eq!(image.get_pixel(centre(1.5), 180).0, [b, b, b]);
+        //PLUGE: below black, black, above black under the red bar
+        assert_eq!(image.get_pixel(centre(5.15), 250).0, [9, 9, 9]);
+        assert_eq!(image.get_pixel(centre(5.5), 250).0, [b, b, b]);
+        assert_eq!(image.get_pixel(centre(5.85), 250).0, [29, 29, 29]);
+        assert_eq!(image.get_pixel(centre(1.8), 250).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn grayscale_rises_in_16_steps() {
+        for mode in [SSTVMode::M1, SSTVMode::R12, SSTVMode::PD290] {
+            let image = Pattern::Grayscale.render(mode);
+            let row: Vec<u8> = (0..image.width()).map(|x| {
+                let [r, g, b] = image.get_pixel(x, image.height() / 2).0;
+                assert!(r == g && g == b);
+                r
+            }).collect();
+            assert!(row.windows(2).all(|w| w[0] <= w[1]), "{:?}", mode);
+            assert_eq!((row[0], *row.last().unwrap()), (0, 255));
+            let mut steps = row.clone();
+            steps.dedup();
+            assert_eq!(steps.len(), 16);
+            //every line is the same
+            assert_eq!(image.get_pixel(7, 0), image.get_pixel(7, image.height() - 1));
+        }
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!("SMPTE".parse::<Pattern>().ok(), Some(Pattern::Bars));
+        assert_eq!("greyscale".parse::<Pattern>().ok(), Some(Pattern::Grayscale));
+        assert_eq!("wedge".parse::<Pattern>().ok(), Some(Pattern::Multiburst));
+        assert_eq!("crosshatch".parse::<Pattern>().ok(), Some(Pattern::Grid));
+        assert!(matches!("plaid".parse::<Pattern>(), Err(Error::InvalidArgument(_))));
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::io::{Seek, Write};
+use std::time::Duration;
+
+//destination for mono samples in -1.0..1.0, whatever the encoder produces goes through one of these
+pub trait SampleSink {
+    fn write_sample(&mut self, sample: f32) -> std::io::Result<()>;
+    //called once the last sample is written
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample * i16::MAX as f32).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+fn hound_to_io(e: hound::Error) -> std::io::Error {
+    match e {
+        hound::Error::IoError(e) => e,
+        e => std::io::Error::other(e),
+    }
+}
+
+//samples are quantised to the writer's 16-bit integer format
+impl<W: Write + Seek> SampleSink for hound::WavWriter<W> {
+    fn write_sample(&mut self, sample: f32) -> std::io::Result<()> {
+        hound::WavWriter::write_sample(self, to_i16(sample)).map_err(hound_to_io)
+    }
+    //brings the header's length fields up to date, finalize() still has to be called to close the file
+    fn flush(&mut self) -> std::io::Result<()> {
+        hound::WavWriter::flush(self).map_err(hound_to_io)
+    }
+}
+
+impl SampleSink for Vec<f32> {
+    fn write_sample(&mut self, sample: f32) -> std::io::Result<()> {
+        self.push(sample);
+        Ok(())
+    }
+}
+
+//headerless 16-bit little endian PCM, as read by aplay, sox or the decoder's stdin mode
+pub struct PcmWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> PcmWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {inner}
+    }
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> SampleSink for PcmWriter<W> {
+    fn write_sample(&mut self, sample: f32) -> std::io::Result<()> {
+        self.inner.write_all(&to_i16(sample).to_le_bytes())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+//collects samples and plays them back as a rodio Source, e.g. appended to a rodio::Sink
+pub struct RodioSource {
+    sample_rate: u32,
+    samples: VecDeque<f32>,
+}
+
+impl RodioSource {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            samples: VecDeque::new(),
+        }
+    }
+}
+
+impl SampleSink for RodioSource {
+    fn write_sample(&mut self, sample: f32) -> std::io::Result<()> {
+        self.samples.push_back(sample);
+        Ok(())
+    }
+}
+
+impl Iterator for RodioSource {
+    type Item = rodio::Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.samples.pop_front()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.samples.len(), Some(self.samples.len()))
+    }
+}
+
+impl rodio::Source for RodioSource {
+    fn current_span_len(&self) -> Option<usize> {
+        Some(self.samples.len())
+    }
+    fn channels(&self) -> rodio::ChannelCount {
+        1
+    }
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.sample_rate
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f64(self.samples.len() as f64 / self.sample_rate as f64))
+    }
+}
@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use image::{Rgb, RgbImage, imageops};
+use serde::Deserialize;
+
+use crate::overlay::{self, TextOverlay};
+use crate::{Error, Fit, SSTVMode};
+
+//layout of a transmission picture, drawn layer by layer over the background
+//coordinates are pixels of `size`, usually an SSTV resolution, and are scaled to each mode's resolution
+//unknown keys are errors rather than silently ignored, e.g. a misspelt colour
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Template {
+    #[serde(default = "default_size")]
+    pub size: (u32, u32),
+    #[serde(default)]
+    pub background: Option<String>,
+    #[serde(default)]
+    pub layers: Vec<Layer>,
+    //image paths in layers are relative to the template file
+    #[serde(skip)]
+    pub dir: PathBuf,
+}
+
+fn default_size() -> (u32, u32) {
+    (320, 256)
+}
+
+//rectangle in template coordinates, a missing side covers the rest of the picture
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Area {
+    pub x: i32,
+    pub y: i32,
+    pub w: Option<u32>,
+    pub h: Option<u32>,
+}
+
+//every layer has the x, y, w and h of its area, spelt out per variant as flatten can't deny unknown keys
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Layer {
+    //picture filled in from the command line by slot name, or a fixed file such as a logo
+    Image {
+        slot: Option<String>,
+        path: Option<String>,
+        #[serde(default)]
+        x: i32,
+        #[serde(default)]
+        y: i32,
+        w: Option<u32>,
+        h: Option<u32>,
+        //stretch, letterbox or crop
+        fit: Option<String>,
+        pad: Option<String>,
+        focus: Option<(f32, f32)>,
+    },
+    //{name} is replaced by the value given for name, {mode} and {utc} are always available
+    Text {
+        text: String,
+        #[serde(default)]
+        x: i32,
+        #[serde(default)]
+        y: i32,
+        w: Option<u32>,
+        h: Option<u32>,
+        anchor: Option<String>,
+        margin: Option<u32>,
+        size: Option<u32>,
+        color: Option<String>,
+        //colour or "none"
+        outline: Option<String>,
+        background: Option<String>,
+    },
+    Rect {
+        #[serde(default)]
+        x: i32,
+        #[serde(default)]
+        y: i32,
+        w: Option<u32>,
+        h: Option<u32>,
+        color: String,
+    },
+    //frame drawn inside the area
+    Border {
+        #[serde(default)]
+        x: i32,
+        #[serde(default)]
+        y: i32,
+        w: Option<u32>,
+        h: Option<u32>,
+        width: Option<u32>,
+        color: String,
+    },
+}
+
+impl Layer {
+    pub fn area(&self) -> Area {
+        match *self {
+            Layer::Image {x, y, w, h, ..} | Layer::Text {x, y, w, h, ..} | Layer::Rect {x, y, w, h, ..} | Layer::Border {x, y, w, h, ..} => {
+                Area {x, y, w, h}
+            }
+        }
+    }
+}
+
+impl Template {
+    //.json files are read as JSON, anything else as TOML
+    pub fn load(path: &str) -> Result<Template, Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::Template(format!("{}: {}", path, e)))?;
+        let mut template: Template = if path.to_lowercase().ends_with(".json") {
+            serde_json::from_str(&text).map_err(|e| Error::Template(format!("{}: {}", path, e)))?
+        }
+        else {
+            toml::from_str(&text).map_err(|e| Error::Template(format!("{}: {}", path, e)))?
+        };
+        template.dir = Path::new(path).parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(template)
+    }
+
+    //slot names the image layers expect from the caller
+    pub fn slots(&self) -> Vec<&str> {
+        self.layers
+            .iter()
+            .filter_map(|layer| match layer {
+                Layer::Image {slot: Some(slot), ..} => Some(slot.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    //picture at the mode's resolution, ready for the encoder
+    pub fn render(
+        &self,
+        mode: SSTVMode,
+        slots: &HashMap<String, RgbImage>,
+        values: &HashMap<String, String>,
+        filter: imageops::FilterType,
+    ) -> Result<RgbImage, Error> {
+        let (width, height) = mode.resolution();
+        let background = optional_color(self.background.as_deref(), "background")?.unwrap_or(Rgb([0, 0, 0]));
+        let mut image = RgbImage::from_pixel(width, height, background);
+
+        let mut values = values.clone();
+        values.entry(String::from("mode")).or_insert_with(|| format!("{:?}", mode));
+        values.entry(String::from("utc")).or_insert_with(|| overlay::utc_timestamp(std::time::SystemTime::now()));
+
+        let sx = width as f64 / self.size.0.max(1) as f64;
+        let sy = height as f64 / self.size.1.max(1) as f64;
+        //template area to output pixels, clipped to nothing rather than negative sizes
+        let place = |area: &Area| -> (i64, i64, u32, u32) {
+            let w = area.w.map(|w| w as i64).unwrap_or(self.size.0 as i64 - area.x as i64).max(0);
+            let h = area.h.map(|h| h as i64).unwrap_or(self.size.1 as i64 - area.y as i64).max(0);
+            let x0 = (area.x as f64 * sx).round() as i64;
+            let y0 = (area.y as f64 * sy).round() as i64;
+            let x1 = ((area.x as i64 + w) as f64 * sx).round() as i64;
+            let y1 = ((area.y as i64 + h) as f64 * sy).round() as i64;
+            (x0, y0, (x1 - x0) as u32, (y1 - y0) as u32)
+        };
+        let scale = sx.min(sy);
+
+        for layer in &self.layers {
+            let (x, y, w, h) = place(&layer.area());
+            match layer {
+                Layer::Image {slot, path, fit, pad, focus, ..} => {
+                    if w == 0 || h == 0 {
+                        continue;
+                    }
+                    let loaded;
+                    let source = match (slot, path) {
+                        (Some(slot), _) if slots.contains_key(slot) => &slots[slot],
+                        (_, Some(path)) => {
+                            loaded = image::open(self.dir.join(path)).map_err(Error::InputImage)?.to_rgb8();
+                            &loaded
+                        }
+                        (Some(slot), None) => return Err(Error::Template(format!("No image for slot {}", slot))),
+                        (None, None) => return Err(Error::Template(String::from("Image layer needs a slot or a path"))),
+                    };
+                    let pad = optional_color(pad.as_deref(), "pad")?.unwrap_or(Rgb([0, 0, 0]));
+                    let fit = match fit.as_deref().map(str::to_lowercase).as_deref() {
+                        None | Some("stretch") => Fit::Stretch,
+                        Some("letterbox") => Fit::Letterbox {pad},
+                        Some("crop") => Fit::Crop {focus: focus.unwrap_or((0.5, 0.5))},
+                        Some(other) => return Err(Error::Template(format!("Unknown fit: {}", other))),
+                    };
+                    let fitted = fit.apply(source, w, h, filter);
+                    imageops::replace(&mut image, &fitted, x, y);
+                }
+                Layer::Text {text, anchor, margin, size, color, outline, background, ..} => {
+                    let mut overlay = TextOverlay::new(&fill(text, &values)?);
+                    if let Some(anchor) = anchor {
+                        overlay.anchor = anchor.parse().map_err(|_| Error::Template(format!("Unknown anchor: {}", anchor)))?;
+                    }
+                    overlay.margin = (margin.unwrap_or(overlay.margin) as f64 * scale).round() as u32;
+                    overlay.scale = ((size.unwrap_or(overlay.scale) as f64 * scale).round() as u32).clamp(1, overlay::MAX_SCALE);
+                    if let Some(color) = optional_color(color.as_deref(), "text")? {
+                        overlay.color = color;
+                    }
+                    if outline.is_some() {
+                        overlay.outline = optional_color(outline.as_deref(), "outline")?;
+                    }
+                    overlay.background = optional_color(background.as_deref(), "text background")?;
+                    overlay.draw_in(&mut image, x, y, w, h);
+                }
+                Layer::Rect {color, ..} => {
+                    overlay::fill_rect(&mut image, x, y, w, h, parse_color(color, "rect")?);
+                }
+                Layer::Border {width, color, ..} => {
+                    let color = parse_color(color, "border")?;
+                    let t = ((width.unwrap_or(1) as f64 * scale).round() as u32).clamp(1, w.min(h).max(1));
+                    overlay::fill_rect(&mut image, x, y, w, t, color);
+                    overlay::fill_rect(&mut image, x, y + h as i64 - t as i64, w, t, color);
+                    overlay::fill_rect(&mut image, x, y, t, h, color);
+                    overlay::fill_rect(&mut image, x + w as i64 - t as i64, y, t, h, color);
+                }
+            }
+        }
+        Ok(image)
+    }
+}
+
+fn parse_color(s: &str, what: &str) -> Result<Rgb<u8>, Error> {
+    overlay::parse_color(s).ok_or_else(|| Error::Template(format!("Invalid {} colour: {}", what, s)))
+}
+
+//missing or "none" is None
+fn optional_color(s: Option<&str>, what: &str) -> Result<Option<Rgb<u8>>, Error> {
+    match s {
+        None => Ok(None),
+        Some(s) if s.eq_ignore_ascii_case("none") => Ok(None),
+        Some(s) => parse_color(s, what).map(Some),
+    }
+}
+
+//replaces each {name} with its value, {{ gives a literal {
+fn fill(text: &str, values: &HashMap<String, String>) -> Result<String, Error> {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        if rest[start + 1..].starts_with('{') {
+            out.push('{');
+            rest = &rest[start + 2..];
+            continue;
+        }
+        let Some(len) = rest[start..].find('}') else {
+            return Err(Error::Template(format!("Unclosed placeholder in: {}", text)));
+        };
+        let name = &rest[start + 1..start + len];
+        let value = values
+            .get(name)
+            .ok_or_else(|| Error::Template(format!("No value for placeholder {{{}}}", name)))?;
+        out.push_str(value);
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML: &str = r##"
+background = "202020"
+
+[[layers]]
+type = "image"
+slot = "main"
+x = 160
+y = 128
+
+[[layers]]
+type = "rect"
+w = 40
+h = 20
+color = "#ff0000"
+
+[[layers]]
+type = "border"
+y = 128
+w = 160
+width = 4
+color = "0000ff"
+
+[[layers]]
+type = "text"
+text = "{call} {mode}"
+y = 40
+h = 40
+anchor = "c"
+outline = "none"
+"##;
+
+    const JSON: &str = r##"{
+        "background": "202020",
+        "layers": [
+            {"type": "image", "slot": "main", "x": 160, "y": 128},
+            {"type": "rect", "w": 40, "h": 20, "color": "#ff0000"},
+            {"type": "border", "y": 128, "w": 160, "width": 4, "color": "0000ff"},
+            {"type": "text", "text": "{call} {mode}", "y": 40, "h": 40, "anchor": "c", "outline": "none"}
+        ]
+    }"##;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn render(template: &Template, slots: &HashMap<String, RgbImage>) -> Result<RgbImage, Error> {
+        template.render(SSTVMode::M4, slots, &values(&[("call", "N0CALL")]), imageops::FilterType::Nearest)
+    }
+
+    fn main_slot() -> HashMap<String, RgbImage> {
+        HashMap::from([(String::from("main"), RgbImage::from_pixel(8, 8, Rgb([0, 255, 0])))])
+    }
+
+    #[test]
+    fn placeholders_are_filled() {
+        let v = values(&[("call", "N0CALL"), ("rsv", "595")]);
+        assert_eq!(fill("de {call} ur {rsv} {call}", &v).unwrap(), "de N0CALL ur 595 N0CALL");
+        assert_eq!(fill("no placeholders", &v).unwrap(), "no placeholders");
+        assert!(matches!(fill("{name}", &v), Err(Error::Template(m)) if m.contains("{name}")));
+        assert!(matches!(fill("de {call", &v), Err(Error::Template(_))));
+        assert_eq!(fill("{{call} is {call}, {{{rsv}}", &v).unwrap(), "{call} is N0CALL, {595}");
+        assert_eq!(fill("{{", &v).unwrap(), "{");
+    }
+
+    #[test]
+    fn layers_scale_to_the_mode() {
+        //320x256 template onto M4's 160x128
+        let template: Template = toml::from_str(TOML).unwrap();
+        assert_eq!(template.slots(), ["main"]);
+        let image = render(&template, &main_slot()).unwrap();
+        assert_eq!(image.dimensions(), (160, 128));
+        assert_eq!(*image.get_pixel(19, 9), Rgb([255, 0, 0]));
+        assert_eq!(*image.get_pixel(20, 10), Rgb([32, 32, 32]));
+        assert_eq!(*image.get_pixel(120, 100), Rgb([0, 255, 0]));
+        assert_eq!(*image.get_pixel(0, 64), Rgb([0, 0, 255]));
+        assert_eq!(*image.get_pixel(40, 127), Rgb([0, 0, 255]));
+        assert_eq!(*image.get_pixel(40, 100), Rgb([32, 32, 32]));
+        //text only inside its area
+        let white = |y0, y1| (y0..y1).any(|y| (0..160).any(|x| *image.get_pixel(x, y) == Rgb([255, 255, 255])));
+        assert!(white(20, 40));
+        assert!(!white(0, 20));
+        assert!(!white(40, 64));
+    }
+
+    #[test]
+    fn missing_slot_is_an_error() {
+        let template: Template = toml::from_str(TOML).unwrap();
+        assert!(matches!(render(&template, &HashMap::new()), Err(Error::Template(m)) if m == "No image for slot main"));
+
+        let template: Template = toml::from_str("[[layers]]\ntype = \"text\"\ntext = \"{op}\"").unwrap();
+        assert!(matches!(render(&template, &HashMap::new()), Err(Error::Template(_))));
+    }
+
+    #[test]
+    fn unknown_keys_are_errors() {
+        let dir = std::env::temp_dir();
+        let cases = [
+            ("toml", "[[layers]]\ntype = \"rect\"\ncolor = \"ff0000\"\ncolour = \"00ff00\""),
+            ("json", r#"{"layers": [{"type": "text", "text": "CQ", "sise": 3}]}"#),
+            ("toml", "bakground = \"ff0000\""),
+        ];
+        for (i, (extension, text)) in cases.into_iter().enumerate() {
+            let path = dir.join(format!("sstv-template-{}-{}.{}", std::process::id(), i, extension));
+            std::fs::write(&path, text).unwrap();
+            let loaded = Template::load(path.to_str().unwrap());
+            std::fs::remove_file(&path).unwrap();
+            assert!(matches!(loaded, Err(Error::Template(m)) if m.contains("unknown field")), "{}", text);
+        }
+    }
+
+    #[test]
+    fn toml_and_json_match() {
+        let dir = std::env::temp_dir();
+        let toml_path = dir.join(format!("sstv-template-{}.toml", std::process::id()));
+        let json_path = dir.join(format!("sstv-template-{}.JSON", std::process::id()));
+        std::fs::write(&toml_path, TOML).unwrap();
+        std::fs::write(&json_path, JSON).unwrap();
+        let from_toml = Template::load(toml_path.to_str().unwrap());
+        let from_json = Template::load(json_path.to_str().unwrap());
+        std::fs::remove_file(&toml_path).unwrap();
+        std::fs::remove_file(&json_path).unwrap();
+
+        let (from_toml, from_json) = (from_toml.unwrap(), from_json.unwrap());
+        assert_eq!(from_toml.dir, dir);
+        assert_eq!(from_json.dir, dir);
+        assert_eq!(from_toml.size, from_json.size);
+        assert_eq!(from_toml.slots(), from_json.slots());
+        assert_eq!(render(&from_toml, &main_slot()).unwrap(), render(&from_json, &main_slot()).unwrap());
+    }
+}